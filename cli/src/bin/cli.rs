@@ -0,0 +1,106 @@
+use std::{env, process, time::Instant};
+
+use chess_engine::{calculate_with_iterative_deepening, perft::{divide, perft}, position::Position};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const BENCH_POSITIONS: [&str; 3] = [
+    START_POSITION,
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w KQ - 0 1"
+];
+
+fn print_usage() {
+    eprintln!("Usage:");
+    eprintln!("  cli perft <depth> [fen]    count leaf nodes, split up by root move");
+    eprintln!("  cli bench [depth]          compare copy-make against make/unmake and time the search");
+}
+
+fn run_perft(args: &[String]) {
+    let depth: u8 = match args.first().and_then(|depth| depth.parse().ok()) {
+        Some(depth) => depth,
+        None => {
+            print_usage();
+            process::exit(1);
+        }
+    };
+    let fen = if args.len() > 1 { args[1..].join(" ") } else { START_POSITION.to_owned() };
+    let position = match Position::from_fen(&fen) {
+        Ok(position) => position,
+        Err(error) => {
+            eprintln!("Invalid FEN: {error}");
+            process::exit(1);
+        }
+    };
+
+    let start = Instant::now();
+    let mut total = 0;
+    for (chess_move,nodes) in divide(&position, depth) {
+        println!("{chess_move}: {nodes}");
+        total += nodes;
+    }
+    let elapsed = start.elapsed();
+
+    println!();
+    println!("Nodes searched: {total}");
+    println!("Time: {} ms ({:.0} nodes/s)", elapsed.as_millis(), total as f64 / elapsed.as_secs_f64().max(f64::EPSILON));
+}
+
+//The way the engine used to walk the tree, a fresh position for every move
+fn perft_copy_make(position: &Position,depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    position.legal_moves().into_iter()
+        .filter_map(|chess_move| position.make_move(chess_move).ok())
+        .map(|new_position| perft_copy_make(&new_position, depth - 1))
+        .sum()
+}
+
+fn nodes_per_second(nodes: u64,seconds: f64) -> f64 {
+    nodes as f64 / seconds.max(f64::EPSILON)
+}
+
+fn run_bench(args: &[String]) {
+    let depth: u8 = args.first().and_then(|depth| depth.parse().ok()).unwrap_or(4);
+
+    println!("{:<12}{:>12}{:>16}{:>16}", "position", "nodes", "copy nps", "make/unmake nps");
+    let (mut total_nodes,mut copy_seconds,mut in_place_seconds) = (0,0.0,0.0);
+    for (index,fen) in BENCH_POSITIONS.iter().enumerate() {
+        let position = Position::from_fen(fen).expect("bench positions are valid");
+
+        let start = Instant::now();
+        let copy_nodes = perft_copy_make(&position, depth);
+        let copy_elapsed = start.elapsed().as_secs_f64();
+
+        let start = Instant::now();
+        let nodes = perft(&position, depth);
+        let in_place_elapsed = start.elapsed().as_secs_f64();
+
+        assert_eq!(copy_nodes, nodes, "both ways of walking the tree have to agree");
+        println!("{:<12}{:>12}{:>16.0}{:>16.0}", index + 1, nodes, nodes_per_second(nodes, copy_elapsed), nodes_per_second(nodes, in_place_elapsed));
+        total_nodes += nodes;
+        copy_seconds += copy_elapsed;
+        in_place_seconds += in_place_elapsed;
+    }
+    println!("{:<12}{:>12}{:>16.0}{:>16.0}", "total", total_nodes, nodes_per_second(total_nodes, copy_seconds), nodes_per_second(total_nodes, in_place_seconds));
+
+    println!();
+    for (index,fen) in BENCH_POSITIONS.iter().enumerate() {
+        let start = Instant::now();
+        let (best_move,score) = calculate_with_iterative_deepening(&Position::from_fen(fen).expect("bench positions are valid"), depth);
+        let best_move = best_move.map(|best_move| best_move.to_string()).unwrap_or_else(|| "none".to_owned());
+        println!("search {} depth {depth}: {best_move} ({score:.2}) in {} ms", index + 1, start.elapsed().as_millis());
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|command| command.as_str()) {
+        Some("perft") => run_perft(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        _ => {
+            print_usage();
+            process::exit(1);
+        }
+    }
+}
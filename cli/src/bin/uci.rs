@@ -0,0 +1,295 @@
+use std::{io::{self, BufRead}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread::{self, JoinHandle}, time::{Duration, Instant}};
+
+use chess_engine::{mate_distance, position::{Position, START_FEN}, search, time_budget, transposition_table::TranspositionTable, Color, SearchControl, SearchInfo, DEFAULT_DEPTH, DEFAULT_TABLE_MB, ENGINE_NAME, MAX_DEPTH, MAX_TABLE_MB};
+
+#[derive(Default)]
+struct GoLimits {
+    depth: Option<u8>,
+    movetime: Option<u64>,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    winc: u64,
+    binc: u64,
+    movestogo: Option<u64>,
+    infinite: bool
+}
+
+impl GoLimits {
+    fn parse(args: &[&str]) -> GoLimits {
+        let mut limits = GoLimits::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut number = || args.next().and_then(|value| value.parse::<u64>().ok());
+            match *arg {
+                "depth" => limits.depth = number().map(|depth| depth.clamp(1, MAX_DEPTH as u64) as u8),
+                "movetime" => limits.movetime = number(),
+                "wtime" => limits.wtime = number(),
+                "btime" => limits.btime = number(),
+                "winc" => limits.winc = number().unwrap_or(0),
+                "binc" => limits.binc = number().unwrap_or(0),
+                "movestogo" => limits.movestogo = number().filter(|moves| *moves > 0),
+                "infinite" => limits.infinite = true,
+                _ => ()
+            }
+        }
+        limits
+    }
+
+    fn budget(&self,whos_move: Color) -> Option<Duration> {
+        let (time, increment) = match whos_move {
+            Color::White => (self.wtime, self.winc),
+            Color::Black => (self.btime, self.binc)
+        };
+        let moves_to_go = self.movestogo.map(|moves| moves.min(u32::MAX as u64) as u32);
+        time_budget(self.movetime.map(Duration::from_millis), time.map(Duration::from_millis), Duration::from_millis(increment), moves_to_go)
+    }
+}
+
+struct Engine {
+    position: Position,
+    //Keys of the positions the moves in the last position command went through
+    history: Vec<u64>,
+    default_depth: u8,
+    //Shared with the search thread, which holds the lock while it runs
+    table: Arc<Mutex<TranspositionTable>>,
+    stop: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>
+}
+
+impl Engine {
+    fn new() -> Engine {
+        Engine {
+            position: Position::from_fen(START_FEN).expect("start position is valid"),
+            history: Vec::new(),
+            default_depth: DEFAULT_DEPTH,
+            table: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_TABLE_MB))),
+            stop: Arc::new(AtomicBool::new(false)),
+            search_thread: None
+        }
+    }
+
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search_thread) = self.search_thread.take() {
+            search_thread.join().expect("search thread panicked");
+        }
+    }
+
+    //position [startpos | fen <fen>] [moves <move> ...]
+    fn set_position(&mut self,args: &[&str]) {
+        let moves_at = args.iter().position(|arg| *arg == "moves").unwrap_or(args.len());
+        let position = match args.first() {
+            Some(&"startpos") => Position::from_fen(START_FEN),
+            Some(&"fen") => Position::from_fen(&args[1..moves_at].join(" ")),
+            _ => {
+                eprintln!("position needs startpos or fen");
+                return;
+            }
+        };
+        let position = match position {
+            Ok(position) => position,
+            Err(error) => {
+                eprintln!("Invalid FEN: {error}");
+                return;
+            }
+        };
+        match position.play_uci_moves(args.get(moves_at + 1..).unwrap_or_default()) {
+            Ok((position,history)) => {
+                self.position = position;
+                self.history = history;
+            },
+            Err(error) => eprintln!("{error}")
+        }
+    }
+
+    fn set_option(&mut self,args: &[&str]) {
+        let value_at = args.iter().position(|arg| *arg == "value");
+        let name = args.get(1..value_at.unwrap_or(args.len())).unwrap_or_default().join(" ");
+        if args.first() != Some(&"name") || name.is_empty() {
+            eprintln!("setoption needs a name");
+            return;
+        }
+        let value = value_at.map(|value_at| args[value_at + 1..].join(" ")).unwrap_or_default();
+        match name.as_str() {
+            "Depth" => match value.parse::<u8>() {
+                Ok(depth) => self.default_depth = depth.clamp(1, MAX_DEPTH),
+                Err(_) => eprintln!("Depth needs a number, got \"{value}\"")
+            },
+            "Hash" => match value.parse::<usize>() {
+                Ok(size_mb) => {
+                    self.stop_search();
+                    *self.table.lock().expect("table lock poisoned") = TranspositionTable::new(size_mb.clamp(1, MAX_TABLE_MB));
+                },
+                Err(_) => eprintln!("Hash needs a number of megabytes, got \"{value}\"")
+            },
+            _ => eprintln!("No such option: {name}")
+        }
+    }
+
+    fn go(&mut self,args: &[&str]) {
+        self.stop_search();
+        let limits = GoLimits::parse(args);
+        let start = Instant::now();
+        let deadline = limits.budget(self.position.whos_move).map(|budget| start + budget);
+        //With a clock to watch the search keeps deepening until time runs out
+        let depth = match limits.depth {
+            Some(depth) => depth,
+            None if limits.infinite || deadline.is_some() => MAX_DEPTH,
+            None => self.default_depth
+        };
+
+        let position = self.position;
+        let history = self.history.clone();
+        let table = self.table.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+        self.search_thread = Some(thread::spawn(move || {
+            let mut control = SearchControl::new(&stop, deadline).with_history(history);
+            let mut table = table.lock().expect("table lock poisoned");
+            let (best_move, _) = search(&position, depth, &mut table, &mut control, &mut |info| println!("{}", info_line(&position, info, start.elapsed())));
+            //In infinite mode bestmove may only be sent once the GUI says stop
+            while limits.infinite && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            match best_move {
+                Some(best_move) => println!("bestmove {best_move}"),
+                None => println!("bestmove 0000")
+            }
+        }));
+    }
+}
+
+//Scores come out of the search from white's side in pawns, UCI wants the side to move's in centipawns
+fn info_line(position: &Position,info: &SearchInfo,elapsed: Duration) -> String {
+    let score = match position.whos_move {
+        Color::White => info.score,
+        Color::Black => -info.score
+    };
+    let score = match mate_distance(score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {}", (score * 100.0).round() as i32)
+    };
+    let millis = elapsed.as_millis().max(1) as u64;
+    let pv: Vec<String> = info.pv.iter().map(|chess_move| chess_move.to_string()).collect();
+    format!("info depth {} score {score} nodes {} nps {} time {millis} pv {}", info.depth, info.nodes, info.nodes * 1000 / millis, pv.join(" "))
+}
+
+fn main() {
+    let mut engine = Engine::new();
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                println!("id name {ENGINE_NAME}");
+                println!("option name Depth type spin default {DEFAULT_DEPTH} min 1 max {MAX_DEPTH}");
+                println!("option name Hash type spin default {DEFAULT_TABLE_MB} min 1 max {MAX_TABLE_MB}");
+                println!("uciok");
+            },
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                engine.stop_search();
+                engine.position = Position::from_fen(START_FEN).expect("start position is valid");
+                engine.history.clear();
+                engine.table.lock().expect("table lock poisoned").clear();
+            },
+            Some("position") => {
+                engine.stop_search();
+                engine.set_position(&words[1..]);
+            },
+            Some("setoption") => engine.set_option(&words[1..]),
+            Some("go") => engine.go(&words[1..]),
+            Some("stop") => engine.stop_search(),
+            Some("quit") => break,
+            Some(command) => eprintln!("Unknown command: {command}"),
+            None => ()
+        }
+    }
+    engine.stop_search();
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use chess_engine::{chess_move::Move, position::{Position, START_FEN}, Color, SearchInfo, DEFAULT_DEPTH, MATE_SCORE, MAX_DEPTH};
+
+    use super::{info_line,Engine,GoLimits};
+
+    #[test]
+    fn go_limits_parse() {
+        let limits = GoLimits::parse(&["wtime","60000","btime","30000","winc","1000","binc","500","movestogo","20","depth","99"]);
+        assert_eq!((limits.wtime,limits.btime,limits.winc,limits.binc,limits.movestogo), (Some(60000),Some(30000),1000,500,Some(20)));
+        assert_eq!(limits.depth, Some(MAX_DEPTH));
+        assert!(!limits.infinite && limits.movetime.is_none());
+
+        let limits = GoLimits::parse(&["infinite","movetime","x","movestogo","0","depth","0"]);
+        assert!(limits.infinite);
+        assert_eq!((limits.movetime,limits.movestogo,limits.depth), (None,None,Some(1)));
+    }
+
+    #[test]
+    fn go_limits_budget() {
+        assert_eq!(GoLimits::parse(&["movetime","1000","wtime","5"]).budget(Color::White), Some(Duration::from_millis(950)));
+        assert_eq!(GoLimits::parse(&["movetime","10"]).budget(Color::Black), Some(Duration::from_millis(1)));
+        //A thirtieth of the clock and most of the increment
+        assert_eq!(GoLimits::parse(&["wtime","60000","winc","1000"]).budget(Color::White), Some(Duration::from_millis(2750)));
+        assert_eq!(GoLimits::parse(&["wtime","60000","btime","20000","movestogo","10"]).budget(Color::Black), Some(Duration::from_millis(2000)));
+        //Never more than the clock holds
+        assert_eq!(GoLimits::parse(&["btime","100","binc","1000","movestogo","1"]).budget(Color::Black), Some(Duration::from_millis(50)));
+        assert_eq!(GoLimits::parse(&["wtime","60000"]).budget(Color::Black), None);
+        assert_eq!(GoLimits::parse(&["depth","5"]).budget(Color::White), None);
+    }
+
+    #[test]
+    fn position_command() {
+        let mut engine = Engine::new();
+        engine.set_position(&["startpos","moves","e2e4","e7e5"]);
+        assert_eq!(engine.position.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2");
+        assert_eq!(engine.history.len(), 2);
+
+        engine.set_position(&["fen","8/8/8/4k3/8/8/4P3/4K3","w","-","-","0","1","moves","e2e4"]);
+        assert_eq!(engine.position.to_fen(), "8/8/8/4k3/4P3/8/8/4K3 b - e3 0 1");
+        assert_eq!(engine.history.len(), 1);
+
+        engine.set_position(&["startpos"]);
+        assert_eq!(engine.position.to_fen(), START_FEN);
+        assert!(engine.history.is_empty());
+
+        //Bad commands leave the position alone
+        for args in [vec!["startpos","moves","e2e5"],vec!["fen","8/8/8"],vec!["moves","e2e4"],vec![]] {
+            engine.set_position(&args);
+            assert_eq!(engine.position.to_fen(), START_FEN);
+        }
+    }
+
+    #[test]
+    fn info_lines() {
+        let position = Position::from_fen(START_FEN).unwrap();
+        let black = position.make_move(Move::from_uci(&position,"e2e4").unwrap()).unwrap();
+        let pv = vec![Move::from_uci(&position,"e2e4").unwrap()];
+        let info = |score: f32,pv: &[Move]| SearchInfo { depth: 3, score, nodes: 1000, best_move: pv.first().copied(), pv: pv.to_vec() };
+        let elapsed = Duration::from_millis(500);
+
+        assert_eq!(info_line(&position, &info(0.5,&pv), elapsed), "info depth 3 score cp 50 nodes 1000 nps 2000 time 500 pv e2e4");
+        assert_eq!(info_line(&black, &info(0.5,&[]), Duration::ZERO), "info depth 3 score cp -50 nodes 1000 nps 1000000 time 1 pv ");
+        assert_eq!(info_line(&position, &info(MATE_SCORE - 1.0,&[]), elapsed), "info depth 3 score mate 1 nodes 1000 nps 2000 time 500 pv ");
+        assert_eq!(info_line(&position, &info(MATE_SCORE - 5.0,&pv), elapsed), "info depth 3 score mate 3 nodes 1000 nps 2000 time 500 pv e2e4");
+        assert_eq!(info_line(&position, &info(-(MATE_SCORE - 4.0),&pv), elapsed), "info depth 3 score mate -2 nodes 1000 nps 2000 time 500 pv e2e4");
+        assert_eq!(info_line(&black, &info(MATE_SCORE - 2.0,&pv), elapsed), "info depth 3 score mate -1 nodes 1000 nps 2000 time 500 pv e2e4");
+    }
+
+    #[test]
+    fn setoption_sets_depth_and_ignores_malformed_commands() {
+        let mut engine = Engine::new();
+        for args in [vec![],vec!["name"],vec!["value","3"],vec!["name","value","3"],vec!["Depth","value","3"]] {
+            engine.set_option(&args);
+        }
+        assert_eq!(engine.default_depth, DEFAULT_DEPTH);
+        engine.set_option(&["name","Depth","value","3"]);
+        assert_eq!(engine.default_depth, 3);
+        engine.set_option(&["name","Depth","value","200"]);
+        assert_eq!(engine.default_depth, MAX_DEPTH);
+    }
+}
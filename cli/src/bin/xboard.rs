@@ -0,0 +1,418 @@
+use std::{io::{self, BufRead}, sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, Sender}, Arc, Mutex}, thread, time::{Duration, Instant}};
+
+use chess_engine::{chess_move::Move, get_game_result, position::{Position, START_FEN}, san::{move_to_san, parse_san}, mate_distance, search, time_budget, transposition_table::TranspositionTable, Color, GameResult, SearchControl, SearchInfo, DEFAULT_DEPTH, DEFAULT_TABLE_MB, ENGINE_NAME, MAX_DEPTH, MAX_TABLE_MB};
+
+//CECP reports mate as a huge score with the distance in moves added on
+const CECP_MATE_SCORE: i32 = 100000;
+
+//Commands from the GUI and finished searches are handled one at a time on the main thread
+enum Event {
+    Command(String),
+    SearchDone { id: u64, best_move: Option<Move> }
+}
+
+struct XBoard {
+    history: Vec<Position>,
+    //None is force mode, the engine only keeps track of the moves
+    engine_color: Option<Color>,
+    depth_limit: Option<u8>,
+    move_time: Option<Duration>,
+    moves_per_session: u32,
+    increment: Duration,
+    engine_clock: Option<Duration>,
+    post: bool,
+    //An abandoned search still holds the lock until it notices it was stopped
+    table: Arc<Mutex<TranspositionTable>>,
+    stop: Arc<AtomicBool>,
+    searching: Option<u64>,
+    next_search_id: u64,
+    events: Sender<Event>
+}
+
+impl XBoard {
+    fn new(events: Sender<Event>) -> XBoard {
+        XBoard {
+            history: vec![Position::from_fen(START_FEN).expect("start position is valid")],
+            engine_color: Some(Color::Black),
+            depth_limit: None,
+            move_time: None,
+            moves_per_session: 0,
+            increment: Duration::ZERO,
+            engine_clock: None,
+            post: false,
+            table: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_TABLE_MB))),
+            stop: Arc::new(AtomicBool::new(false)),
+            searching: None,
+            next_search_id: 0,
+            events
+        }
+    }
+
+    fn position(&self) -> Position {
+        *self.history.last().expect("history always holds the starting position")
+    }
+
+    //Stops the search and forgets about it, whatever it finds is not played
+    fn abandon_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.searching = None;
+    }
+
+    //Keys of the positions before the current one, for the repetition rules
+    fn earlier_keys(&self) -> Vec<u64> {
+        self.history[..self.history.len() - 1].iter().map(|position| position.key()).collect()
+    }
+
+    fn game_result(&self) -> GameResult {
+        get_game_result(&self.position(), &self.earlier_keys())
+    }
+
+    fn new_game(&mut self) {
+        self.abandon_search();
+        self.history = vec![Position::from_fen(START_FEN).expect("start position is valid")];
+        self.engine_color = Some(Color::Black);
+        self.depth_limit = None;
+    }
+
+    //Plays a move and announces the result when it ends the game
+    fn play(&mut self,chess_move: Move) -> GameResult {
+        let position = self.position().make_move(chess_move).expect("legal moves can always be played");
+        self.history.push(position);
+        let game_result = self.game_result();
+        if let Some(line) = result_line(game_result) {
+            println!("{line}");
+        }
+        game_result
+    }
+
+    fn user_move(&mut self,text: &str) {
+        let position = self.position();
+        //Coordinate notation is what usermove=1 asks for, SAN is accepted as well
+        let chess_move = Move::from_uci(&position, text).ok().or_else(|| parse_san(&position, text).ok());
+        match chess_move {
+            Some(chess_move) => {
+                if self.play(chess_move) == GameResult::Ongoing {
+                    self.think_if_engine_to_move();
+                }
+            },
+            None => println!("Illegal move: {text}")
+        }
+    }
+
+    fn think_if_engine_to_move(&mut self) {
+        if self.searching.is_none() && self.engine_color == Some(self.position().whos_move) && self.game_result() == GameResult::Ongoing {
+            self.think();
+        }
+    }
+
+    //A fixed time per move wins over the clock, with neither the search goes to a fixed depth
+    fn budget(&self) -> Option<Duration> {
+        let moves_to_go = match self.moves_per_session {
+            0 => None,
+            moves_per_session => Some(moves_per_session - (self.position().fullmove_number - 1) % moves_per_session)
+        };
+        time_budget(self.move_time, self.engine_clock, self.increment, moves_to_go)
+    }
+
+    fn think(&mut self) {
+        let position = self.position();
+        let start = Instant::now();
+        let deadline = self.budget().map(|budget| start + budget);
+        let depth = self.depth_limit.unwrap_or(if deadline.is_some() { MAX_DEPTH } else { DEFAULT_DEPTH });
+        let post = self.post;
+        let history = self.earlier_keys();
+
+        let id = self.next_search_id;
+        self.next_search_id += 1;
+        self.searching = Some(id);
+        let stop = Arc::new(AtomicBool::new(false));
+        self.stop = stop.clone();
+        let events = self.events.clone();
+        let table = self.table.clone();
+        thread::spawn(move || {
+            let mut control = SearchControl::new(&stop, deadline).with_history(history);
+            let mut table = table.lock().expect("table lock poisoned");
+            let (best_move, _) = search(&position, depth, &mut table, &mut control, &mut |info| {
+                if post {
+                    println!("{}", thinking_line(&position, info, start.elapsed()));
+                }
+            });
+            //The main thread is gone once quit has been handled
+            let _ = events.send(Event::SearchDone { id, best_move });
+        });
+    }
+
+    fn search_done(&mut self,id: u64,best_move: Option<Move>) {
+        if self.searching != Some(id) {
+            return;
+        }
+        self.searching = None;
+        if let Some(best_move) = best_move {
+            println!("move {best_move}");
+            self.play(best_move);
+        }
+    }
+
+    fn take_back(&mut self,plies: usize) {
+        self.abandon_search();
+        for _ in 0..plies {
+            if self.history.len() > 1 {
+                self.history.pop();
+            }
+        }
+    }
+
+    //level MPS BASE INC, the base time is minutes or minutes:seconds
+    fn set_level(&mut self,args: &[&str]) {
+        let [moves_per_session, _, increment] = args else {
+            println!("Error (wrong number of arguments): level");
+            return;
+        };
+        self.moves_per_session = moves_per_session.parse().unwrap_or(0);
+        self.increment = increment.parse::<f64>().ok().filter(|seconds| *seconds >= 0.0).map(Duration::from_secs_f64).unwrap_or(Duration::ZERO);
+        self.move_time = None;
+    }
+
+    //Returns false once the GUI has asked to quit
+    fn command(&mut self,line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some(command) = words.first().copied() else { return true };
+        let argument = words.get(1).copied().unwrap_or_default();
+        match command {
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer" | "name" | "rating" | "otim" => (),
+            "protover" => println!("feature myname=\"{ENGINE_NAME}\" setboard=1 usermove=1 ping=1 playother=1 memory=1 san=0 colors=0 sigint=0 sigterm=0 analyze=0 done=1"),
+            "new" => {
+                self.new_game();
+                self.table.lock().expect("table lock poisoned").clear();
+            },
+            "memory" => match argument.parse::<usize>() {
+                Ok(size_mb) => {
+                    self.abandon_search();
+                    *self.table.lock().expect("table lock poisoned") = TranspositionTable::new(size_mb.clamp(1, MAX_TABLE_MB));
+                },
+                Err(_) => println!("Error (bad memory size): {line}")
+            },
+            "setboard" => {
+                self.abandon_search();
+                match Position::from_fen(&words[1..].join(" ")) {
+                    Ok(position) => self.history = vec![position],
+                    Err(error) => println!("tellusererror Illegal position: {error}")
+                }
+            },
+            "usermove" => self.user_move(argument),
+            "go" => {
+                self.engine_color = Some(self.position().whos_move);
+                self.think_if_engine_to_move();
+            },
+            "playother" => self.engine_color = Some(self.position().whos_move.opponent()),
+            "force" | "result" => {
+                self.abandon_search();
+                self.engine_color = None;
+            },
+            "?" => self.stop.store(true, Ordering::Relaxed),
+            "ping" => println!("pong {argument}"),
+            "level" => self.set_level(&words[1..]),
+            "st" => match argument.parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => self.move_time = Some(Duration::from_secs_f64(seconds)),
+                _ => println!("Error (bad time): {line}")
+            },
+            "sd" => match argument.parse::<u8>() {
+                Ok(depth) => self.depth_limit = Some(depth.clamp(1, MAX_DEPTH)),
+                Err(_) => println!("Error (bad depth): {line}")
+            },
+            "time" => self.engine_clock = argument.parse::<u64>().ok().map(|centiseconds| Duration::from_millis(centiseconds * 10)),
+            "undo" => self.take_back(1),
+            "remove" => self.take_back(2),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => {
+                self.abandon_search();
+                return false;
+            },
+            _ => println!("Error (unknown command): {command}")
+        }
+        true
+    }
+}
+
+//What is sent when a game ends, nothing while it is still going
+fn result_line(game_result: GameResult) -> Option<String> {
+    match game_result {
+        GameResult::Checkmate(Color::White) => Some("1-0 {White mates}".to_owned()),
+        GameResult::Checkmate(Color::Black) => Some("0-1 {Black mates}".to_owned()),
+        GameResult::Stalemate => Some("1/2-1/2 {Stalemate}".to_owned()),
+        GameResult::Draw(reason) => Some(format!("1/2-1/2 {{Draw by {reason}}}")),
+        GameResult::Ongoing => None
+    }
+}
+
+//ply score time nodes pv, with the score in centipawns for the engine and the time in centiseconds
+fn thinking_line(position: &Position,info: &SearchInfo,elapsed: Duration) -> String {
+    let score = match position.whos_move {
+        Color::White => info.score,
+        Color::Black => -info.score
+    };
+    let score = match mate_distance(score) {
+        Some(moves) => if moves > 0 { CECP_MATE_SCORE + moves } else { -CECP_MATE_SCORE + moves },
+        None => (score * 100.0).round() as i32
+    };
+
+    let mut line_position = *position;
+    let mut pv = Vec::new();
+    for chess_move in &info.pv {
+        pv.push(move_to_san(&line_position, *chess_move));
+        line_position = line_position.make_move(*chess_move).expect("the pv only holds legal moves");
+    }
+    format!("{} {score} {} {} {}", info.depth, elapsed.as_millis() / 10, info.nodes, pv.join(" "))
+}
+
+fn main() {
+    let (events, receiver) = mpsc::channel();
+    let commands = events.clone();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if commands.send(Event::Command(line)).is_err() {
+                return;
+            }
+        }
+        let _ = commands.send(Event::Command("quit".to_owned()));
+    });
+
+    let mut xboard = XBoard::new(events);
+    for event in receiver {
+        match event {
+            Event::Command(line) => if !xboard.command(&line) {
+                break;
+            },
+            Event::SearchDone { id, best_move } => xboard.search_done(id, best_move)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{sync::mpsc::{self, Receiver}, time::Duration};
+
+    use chess_engine::{chess_move::Move, position::{Position, START_FEN}, Color, DrawReason, GameResult, SearchInfo, MATE_SCORE};
+
+    use super::{result_line,thinking_line,Event,XBoard,CECP_MATE_SCORE};
+
+    fn xboard(commands: &[&str]) -> (XBoard,Receiver<Event>) {
+        let (events, receiver) = mpsc::channel();
+        let mut xboard = XBoard::new(events);
+        for command in commands {
+            assert!(xboard.command(command));
+        }
+        (xboard,receiver)
+    }
+
+    //Waits for the search that was started and hands its move to the engine
+    fn finish_search(xboard: &mut XBoard,receiver: &Receiver<Event>) {
+        match receiver.recv_timeout(Duration::from_secs(120)) {
+            Ok(Event::SearchDone { id, best_move }) => xboard.search_done(id, best_move),
+            _ => panic!("no search finished")
+        }
+    }
+
+    #[test]
+    fn new_and_setboard() {
+        let fen = "8/8/8/4k3/8/8/4P3/4K3 w - - 0 1";
+        let (mut xboard,_receiver) = xboard(&["force","sd 3",&format!("setboard {fen}")]);
+        assert_eq!(xboard.position().to_fen(), fen);
+        assert!(xboard.command("setboard 8/8/8"));
+        assert_eq!(xboard.position().to_fen(), fen);
+
+        assert!(xboard.command("new"));
+        assert_eq!(xboard.position().to_fen(), START_FEN);
+        assert_eq!((xboard.engine_color,xboard.depth_limit), (Some(Color::Black),None));
+    }
+
+    #[test]
+    fn usermove_in_force_mode() {
+        let (mut xboard,_receiver) = xboard(&["force","usermove e2e4"]);
+        assert_eq!(xboard.history.len(), 2);
+        assert!(xboard.command("usermove e2e5"));
+        assert_eq!(xboard.history.len(), 2);
+        //SAN is understood too
+        assert!(xboard.command("usermove e5"));
+        assert_eq!(xboard.history.len(), 3);
+        assert_eq!(xboard.searching, None);
+
+        assert!(xboard.command("remove"));
+        assert_eq!(xboard.history.len(), 1);
+    }
+
+    #[test]
+    fn go_plays_a_move() {
+        let (mut xboard,receiver) = xboard(&["new","sd 2","force","usermove e2e4","go"]);
+        assert_eq!(xboard.engine_color, Some(Color::Black));
+        assert!(xboard.searching.is_some());
+        finish_search(&mut xboard, &receiver);
+        assert_eq!((xboard.history.len(),xboard.searching), (3,None));
+        assert_eq!(xboard.position().whos_move, Color::White);
+
+        //The engine answers a move on its own once it has a side
+        assert!(xboard.command("usermove d2d4"));
+        assert!(xboard.searching.is_some());
+        finish_search(&mut xboard, &receiver);
+        assert_eq!(xboard.history.len(), 5);
+    }
+
+    #[test]
+    fn abandoned_search_is_not_played() {
+        let (mut xboard,receiver) = xboard(&["new","sd 2","usermove e2e4"]);
+        assert!(xboard.searching.is_some());
+        assert!(xboard.command("force"));
+        assert_eq!((xboard.searching,xboard.engine_color), (None,None));
+        finish_search(&mut xboard, &receiver);
+        assert_eq!(xboard.history.len(), 2);
+    }
+
+    #[test]
+    fn time_controls() {
+        let (mut xboard,_receiver) = xboard(&["level 40 5 2","time 30000"]);
+        assert_eq!((xboard.moves_per_session,xboard.increment), (40,Duration::from_secs(2)));
+        //A fortieth of the clock and most of the increment
+        assert_eq!(xboard.budget(), Some(Duration::from_millis(9000)));
+
+        assert!(xboard.command("st 3"));
+        assert_eq!(xboard.budget(), Some(Duration::from_millis(2950)));
+        assert!(xboard.command("level 0 1 0"));
+        assert_eq!(xboard.budget(), Some(Duration::from_millis(10000)));
+        assert!(xboard.command("level 40 5"));
+        assert_eq!(xboard.moves_per_session, 0);
+
+        assert!(xboard.command("sd 0"));
+        assert_eq!(xboard.depth_limit, Some(1));
+        assert!(xboard.command("sd x"));
+        assert_eq!(xboard.depth_limit, Some(1));
+        assert!(!xboard.command("quit"));
+    }
+
+    #[test]
+    fn game_ends_with_a_result() {
+        let (mut xboard,_receiver) = xboard(&["new","force","usermove f2f3","usermove e7e5","usermove g2g4","usermove d8h4"]);
+        assert_eq!(xboard.game_result(), GameResult::Checkmate(Color::Black));
+        assert!(xboard.command("go"));
+        assert_eq!(xboard.searching, None);
+
+        assert_eq!(result_line(GameResult::Checkmate(Color::Black)).as_deref(), Some("0-1 {Black mates}"));
+        assert_eq!(result_line(GameResult::Checkmate(Color::White)).as_deref(), Some("1-0 {White mates}"));
+        assert_eq!(result_line(GameResult::Draw(DrawReason::ThreefoldRepetition)).as_deref(), Some("1/2-1/2 {Draw by threefold repetition}"));
+        assert_eq!(result_line(GameResult::Ongoing), None);
+    }
+
+    #[test]
+    fn thinking_lines() {
+        let position = Position::from_fen(START_FEN).unwrap();
+        let pv = vec![Move::from_uci(&position,"e2e4").unwrap(),Move::from_uci(&position.make_move(Move::from_uci(&position,"e2e4").unwrap()).unwrap(),"e7e5").unwrap()];
+        let info = |score: f32,pv: &[Move]| SearchInfo { depth: 4, score, nodes: 1234, best_move: pv.first().copied(), pv: pv.to_vec() };
+        let elapsed = Duration::from_millis(1500);
+
+        assert_eq!(thinking_line(&position, &info(0.25,&pv), elapsed), "4 25 150 1234 e4 e5");
+        assert_eq!(thinking_line(&position, &info(MATE_SCORE - 3.0,&pv[..1]), elapsed), format!("4 {} 150 1234 e4", CECP_MATE_SCORE + 2));
+        assert_eq!(thinking_line(&position, &info(-(MATE_SCORE - 2.0),&[]), elapsed), format!("4 {} 150 1234 ", -CECP_MATE_SCORE - 1));
+    }
+}
@@ -0,0 +1,120 @@
+use super::{offset_square, Color, KNIGHT_DIRECTIONS, QUEEN_DIRECTIONS};
+
+//One bit per square using the same indexing as the board, bit 0 is a8 and bit 63 is h1
+pub type Bitboard = u64;
+
+pub const fn bit(square: usize) -> Bitboard {
+    1 << square
+}
+
+//Removes the lowest set square from the set and returns it
+pub fn pop_lsb(bitboard: &mut Bitboard) -> usize {
+    let square = bitboard.trailing_zeros() as usize;
+    *bitboard &= *bitboard - 1;
+    square
+}
+
+//Iterates over the squares of a set, lowest index first
+pub fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || if bitboard == 0 { None } else { Some(pop_lsb(&mut bitboard)) })
+}
+
+const fn leaper_attacks(directions: &[(i8,i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut i = 0;
+        while i < directions.len() {
+            if let Some(target) = offset_square(square, directions[i].0, directions[i].1) {
+                table[square] |= bit(target);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn pawn_attack_table(row_offset: i8) -> [Bitboard; 64] {
+    leaper_attacks(&[(row_offset,-1),(row_offset,1)])
+}
+
+//Every square from the starting square outwards in one direction, not including the starting square
+const fn ray_table(row_offset: i8,col_offset: i8) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut current = square;
+        while let Some(target) = offset_square(current, row_offset, col_offset) {
+            table[square] |= bit(target);
+            current = target;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn build_rays() -> [[Bitboard; 64]; 8] {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        rays[direction] = ray_table(QUEEN_DIRECTIONS[direction].0, QUEEN_DIRECTIONS[direction].1);
+        direction += 1;
+    }
+    rays
+}
+
+static KNIGHT_ATTACKS: [Bitboard; 64] = leaper_attacks(&KNIGHT_DIRECTIONS);
+static KING_ATTACKS: [Bitboard; 64] = leaper_attacks(&QUEEN_DIRECTIONS);
+static WHITE_PAWN_ATTACKS: [Bitboard; 64] = pawn_attack_table(-1);
+static BLACK_PAWN_ATTACKS: [Bitboard; 64] = pawn_attack_table(1);
+static RAYS: [[Bitboard; 64]; 8] = build_rays();
+
+//Indexes into QUEEN_DIRECTIONS, the first four are the rook directions and the last four the bishop ones
+const ROOK_RAYS: [usize; 4] = [0,1,2,3];
+const BISHOP_RAYS: [usize; 4] = [4,5,6,7];
+
+pub fn knight_attacks(square: usize) -> Bitboard {
+    KNIGHT_ATTACKS[square]
+}
+
+pub fn king_attacks(square: usize) -> Bitboard {
+    KING_ATTACKS[square]
+}
+
+//Squares a pawn of this color standing on square attacks
+pub fn pawn_attacks(color: Color,square: usize) -> Bitboard {
+    match color {
+        Color::White => WHITE_PAWN_ATTACKS[square],
+        Color::Black => BLACK_PAWN_ATTACKS[square]
+    }
+}
+
+//Walks the ray until the first occupied square, which is included so captures fall out of the same set
+fn ray_attacks(square: usize,occupied: Bitboard,direction: usize) -> Bitboard {
+    let ray = RAYS[direction][square];
+    let blockers = ray & occupied;
+    if blockers == 0 {
+        return ray;
+    }
+    let (row_offset,col_offset) = QUEEN_DIRECTIONS[direction];
+    let blocker = if row_offset * 8 + col_offset > 0 {
+        blockers.trailing_zeros() as usize
+    }
+    else {
+        63 - blockers.leading_zeros() as usize
+    };
+    ray ^ RAYS[direction][blocker]
+}
+
+pub fn bishop_attacks(square: usize,occupied: Bitboard) -> Bitboard {
+    BISHOP_RAYS.iter().fold(0, |attacks,direction| attacks | ray_attacks(square, occupied, *direction))
+}
+
+pub fn rook_attacks(square: usize,occupied: Bitboard) -> Bitboard {
+    ROOK_RAYS.iter().fold(0, |attacks,direction| attacks | ray_attacks(square, occupied, *direction))
+}
+
+pub fn queen_attacks(square: usize,occupied: Bitboard) -> Bitboard {
+    bishop_attacks(square, occupied) | rook_attacks(square, occupied)
+}
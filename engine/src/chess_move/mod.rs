@@ -0,0 +1,98 @@
+use core::fmt;
+use std::error::Error;
+
+use super::{parse_square, position::Position, PieceType};
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum UciMoveError {
+    BadSyntax(String),
+    IllegalMove(String)
+}
+
+impl fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciMoveError::BadSyntax(text) => write!(f, "\"{text}\" is not a move in coordinate notation"),
+            UciMoveError::IllegalMove(text) => write!(f, "\"{text}\" is not a legal move here")
+        }
+    }
+}
+
+impl Error for UciMoveError {}
+
+//A move knows both of its squares and what kind of move it is, so it can be played without looking at the board again
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: Option<PieceType>,
+    pub flags: u8
+}
+
+impl Move {
+    pub const QUIET: u8 = 0;
+    pub const CAPTURE: u8 = 1;
+    pub const CASTLE: u8 = 2;
+    pub const EN_PASSANT: u8 = 4;
+    pub const DOUBLE_PUSH: u8 = 8;
+
+    pub fn new(from: usize,to: usize,promotion: Option<PieceType>,flags: u8) -> Move {
+        Move { from: from as u8, to: to as u8, promotion, flags }
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.flags & Move::CAPTURE != 0
+    }
+
+    pub fn is_castle(&self) -> bool {
+        self.flags & Move::CASTLE != 0
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags & Move::EN_PASSANT != 0
+    }
+
+    pub fn is_double_push(&self) -> bool {
+        self.flags & Move::DOUBLE_PUSH != 0
+    }
+
+    //Reads coordinate notation as used by UCI, castling is the king's two square move such as "e1g1"
+    pub fn from_uci(position: &Position,text: &str) -> Result<Move,UciMoveError> {
+        let bad_syntax = || UciMoveError::BadSyntax(text.to_owned());
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return Err(bad_syntax());
+        }
+        let from = parse_square(&text[0..2]).ok_or_else(bad_syntax)?;
+        let to = parse_square(&text[2..4]).ok_or_else(bad_syntax)?;
+        let promotion = match &text[4..] {
+            "" => None,
+            "q" => Some(PieceType::Queen),
+            "r" => Some(PieceType::Rook),
+            "b" => Some(PieceType::Bishop),
+            "n" => Some(PieceType::Knight),
+            _ => return Err(bad_syntax())
+        };
+        position.find_move(from, to, promotion).ok_or_else(|| UciMoveError::IllegalMove(text.to_owned()))
+    }
+}
+
+//Coordinate notation such as "e2e4" or "e7e8q"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", square_name(self.from as usize), square_name(self.to as usize))?;
+        match self.promotion {
+            Some(PieceType::Queen) => write!(f, "q"),
+            Some(PieceType::Rook) => write!(f, "r"),
+            Some(PieceType::Bishop) => write!(f, "b"),
+            Some(PieceType::Knight) => write!(f, "n"),
+            _ => Ok(())
+        }
+    }
+}
+
+//Turns a board index back into a square name, 0 is "a8" and 63 is "h1"
+pub fn square_name(square: usize) -> String {
+    let file = (b'a' + (square % 8) as u8) as char;
+    let rank = (b'8' - (square / 8) as u8) as char;
+    format!("{file}{rank}")
+}
@@ -0,0 +1,1857 @@
+use core::fmt;
+use std::{error::Error, sync::atomic::{AtomicBool, Ordering}, time::{Duration, Instant}};
+
+use self::{bitboard::{bishop_attacks, bit, king_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks, squares}, chess_move::Move, position::Position, transposition_table::{Bound, TranspositionTable}};
+pub mod bitboard;
+pub mod chess_move;
+pub mod perft;
+pub mod pgn;
+pub mod position;
+pub mod san;
+pub mod transposition_table;
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub enum Color {
+    White,
+    Black,
+}
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King
+}
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub struct Piece {
+    pub color: Color,
+    pub piece_type: PieceType,
+    value: u8
+}
+
+#[derive(Clone,PartialEq,Debug,Copy,Default)]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool
+}
+
+impl CastlingRights {
+    //Parses the castling field of a FEN string such as "KQkq" or "-"
+    pub fn from_fen(field: &str) -> Option<CastlingRights> {
+        let mut castling_rights = CastlingRights::default();
+        if field == "-" {
+            return Some(castling_rights);
+        }
+        for c in field.chars() {
+            match c {
+                'K' => castling_rights.white_king_side = true,
+                'Q' => castling_rights.white_queen_side = true,
+                'k' => castling_rights.black_king_side = true,
+                'q' => castling_rights.black_queen_side = true,
+                _ => return None
+            }
+        }
+        Some(castling_rights)
+    }
+
+    //Used when a FEN has no castling field, kings and rooks still on their starting squares keep their rights
+    pub fn from_position(position: &Position) -> CastlingRights {
+        let is_piece = |square: usize,piece: Piece| position.piece_at(square) == Some(piece);
+        let white_king_home = is_piece(60, WHITE_KING);
+        let black_king_home = is_piece(4, BLACK_KING);
+        CastlingRights {
+            white_king_side: white_king_home && is_piece(63, WHITE_ROOK),
+            white_queen_side: white_king_home && is_piece(56, WHITE_ROOK),
+            black_king_side: black_king_home && is_piece(7, BLACK_ROOK),
+            black_queen_side: black_king_home && is_piece(0, BLACK_ROOK)
+        }
+    }
+
+    pub fn king_side(&self,color: Color) -> bool {
+        match color {
+            Color::White => self.white_king_side,
+            Color::Black => self.black_king_side
+        }
+    }
+
+    pub fn queen_side(&self,color: Color) -> bool {
+        match color {
+            Color::White => self.white_queen_side,
+            Color::Black => self.black_queen_side
+        }
+    }
+
+    //Any move starting or landing on a king or rook home square loses the matching rights
+    pub fn update(&self,from: usize,to: usize) -> CastlingRights {
+        let mut castling_rights = *self;
+        for square in [from,to] {
+            match square {
+                60 => { castling_rights.white_king_side = false; castling_rights.white_queen_side = false; },
+                63 => castling_rights.white_king_side = false,
+                56 => castling_rights.white_queen_side = false,
+                4 => { castling_rights.black_king_side = false; castling_rights.black_queen_side = false; },
+                7 => castling_rights.black_king_side = false,
+                0 => castling_rights.black_queen_side = false,
+                _ => ()
+            }
+        }
+        castling_rights
+    }
+}
+
+//Writes the rights the way the castling field of a FEN string expects them
+impl fmt::Display for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == CastlingRights::default() {
+            return write!(f, "-");
+        }
+        let rights = [(self.white_king_side,'K'),(self.white_queen_side,'Q'),(self.black_king_side,'k'),(self.black_queen_side,'q')];
+        for (has_right,c) in rights {
+            if has_right {
+                write!(f, "{c}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ChessEngineError {
+    message: String
+}
+
+impl fmt::Display for ChessEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chess Engine Error: {}", self.message)
+    }
+}
+
+impl Error for ChessEngineError {}
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub enum DrawReason {
+    InsufficientMaterial,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    FiftyMoveRule,
+    SeventyFiveMoveRule
+}
+
+impl fmt::Display for DrawReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DrawReason::InsufficientMaterial => write!(f, "insufficient material"),
+            DrawReason::ThreefoldRepetition => write!(f, "threefold repetition"),
+            DrawReason::FivefoldRepetition => write!(f, "fivefold repetition"),
+            DrawReason::FiftyMoveRule => write!(f, "the fifty-move rule"),
+            DrawReason::SeventyFiveMoveRule => write!(f, "the seventy-five-move rule")
+        }
+    }
+}
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub enum GameResult {
+    Ongoing,
+    Checkmate(Color), //Holds the winning color
+    Stalemate,
+    Draw(DrawReason)
+}
+
+
+
+const WHITE_PAWN: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::Pawn,
+    value: 1
+};
+
+const WHITE_KNIGHT: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::Knight,
+    value: 3
+};
+
+const WHITE_BISHOP: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::Bishop,
+    value: 3
+};
+
+const WHITE_ROOK: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::Rook,
+    value: 5
+};
+
+const WHITE_QUEEN: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::Queen,
+    value: 9
+};
+
+const WHITE_KING: Piece = Piece {
+    color: Color::White,
+    piece_type: PieceType::King,
+    value: 205
+};
+
+const BLACK_PAWN: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::Pawn,
+    value: 1
+};
+
+const BLACK_KNIGHT: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::Knight,
+    value: 3
+};
+
+const BLACK_BISHOP: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::Bishop,
+    value: 3
+};
+
+const BLACK_ROOK: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::Rook,
+    value: 5
+};
+
+const BLACK_QUEEN: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::Queen,
+    value: 9
+};
+
+const BLACK_KING: Piece = Piece {
+    color: Color::Black,
+    piece_type: PieceType::King,
+    value: 205
+};
+
+const PROMOTION_PIECES: [PieceType;4] = [PieceType::Queen,PieceType::Knight,PieceType::Rook,PieceType::Bishop];
+
+const KNIGHT_DIRECTIONS: [(i8,i8);8] = [(-2,-1),(-2,1),(-1,-2),(-1,2),(1,-2),(1,2),(2,-1),(2,1)];
+const QUEEN_DIRECTIONS: [(i8,i8);8] = [(0,-1),(0,1),(1,0),(-1,0),(-1,-1),(1,1),(1,-1),(-1,1)];
+
+//Returns the square reached by stepping rows/cols away from position, or None if that walks off the board
+const fn offset_square(position: usize, row_offset: i8, col_offset: i8) -> Option<usize> {
+    let row = (position / 8) as i8 + row_offset;
+    let col = (position % 8) as i8 + col_offset;
+    if row < 0 || row >= 8 || col < 0 || col >= 8 {
+        return None;
+    }
+    Some((row * 8 + col) as usize)
+}
+
+impl Piece {
+    pub fn new(color: Color,piece_type: PieceType) -> Piece {
+        match (color,piece_type) {
+            (Color::White,PieceType::Pawn) => WHITE_PAWN,
+            (Color::White,PieceType::Knight) => WHITE_KNIGHT,
+            (Color::White,PieceType::Bishop) => WHITE_BISHOP,
+            (Color::White,PieceType::Rook) => WHITE_ROOK,
+            (Color::White,PieceType::Queen) => WHITE_QUEEN,
+            (Color::White,PieceType::King) => WHITE_KING,
+            (Color::Black,PieceType::Pawn) => BLACK_PAWN,
+            (Color::Black,PieceType::Knight) => BLACK_KNIGHT,
+            (Color::Black,PieceType::Bishop) => BLACK_BISHOP,
+            (Color::Black,PieceType::Rook) => BLACK_ROOK,
+            (Color::Black,PieceType::Queen) => BLACK_QUEEN,
+            (Color::Black,PieceType::King) => BLACK_KING
+        }
+    }
+
+    pub fn get_moves(&self,position: &Position,square: usize) -> Result<Vec<Move>,ChessEngineError> {
+        if position.piece_at(square) != Some(*self) {
+            return Err(ChessEngineError {message: "Piece not on that square".to_owned()});
+        }
+
+        let own = position.occupancy[self.color as usize];
+        let enemy = position.occupancy[self.color.opponent() as usize];
+        let occupied = own | enemy;
+        let mut moves: Vec<Move> = vec![];
+
+        let targets = match self.piece_type {
+            PieceType::Pawn => {
+                let (direction,starting_row) = match self.color {
+                    Color::White => (-1,6),
+                    Color::Black => (1,1)
+                };
+
+                let mut targets = pawn_attacks(self.color, square) & enemy;
+                if let Some(forward_one) = offset_square(square, direction, 0) {
+                    if occupied & bit(forward_one) == 0 {
+                        targets |= bit(forward_one);
+                        if let Some(forward_two) = offset_square(square, 2 * direction, 0) {
+                            if square / 8 == starting_row && occupied & bit(forward_two) == 0 {
+                                moves.push(Move::new(square, forward_two, None, Move::DOUBLE_PUSH));
+                            }
+                        }
+                    }
+                }
+                //The pawn taken stands beside this one, on the en passant square's file
+                if let Some(en_passant) = position.en_passant {
+                    let captured = bit((square / 8) * 8 + en_passant % 8);
+                    if pawn_attacks(self.color, square) & bit(en_passant) != 0
+                        && position.pieces[self.color.opponent() as usize][PieceType::Pawn as usize] & captured != 0 {
+                        moves.push(Move::new(square, en_passant, None, Move::CAPTURE | Move::EN_PASSANT));
+                    }
+                }
+                targets
+            },
+            PieceType::Knight => knight_attacks(square) & !own,
+            PieceType::Bishop => bishop_attacks(square, occupied) & !own,
+            PieceType::Rook => rook_attacks(square, occupied) & !own,
+            PieceType::Queen => queen_attacks(square, occupied) & !own,
+            PieceType::King => {
+                //Castling logic, the landing square itself is checked by get_legal_moves like any other move
+                let (home_square,opponent) = (match self.color { Color::White => 60, Color::Black => 4 },self.color.opponent());
+                let rooks = position.pieces[self.color as usize][PieceType::Rook as usize];
+                if square == home_square && !is_square_attacked(position, square, opponent) {
+                    if position.castling_rights.king_side(self.color) && rooks & bit(square + 3) != 0
+                        && occupied & (bit(square + 1) | bit(square + 2)) == 0
+                        && !is_square_attacked(position, square + 1, opponent) {
+                        moves.push(Move::new(square, square + 2, None, Move::CASTLE));
+                    }
+                    if position.castling_rights.queen_side(self.color) && rooks & bit(square - 4) != 0
+                        && occupied & (bit(square - 1) | bit(square - 2) | bit(square - 3)) == 0
+                        && !is_square_attacked(position, square - 1, opponent) {
+                        moves.push(Move::new(square, square - 2, None, Move::CASTLE));
+                    }
+                }
+                king_attacks(square) & !own
+            }
+        };
+
+        for to in squares(targets) {
+            let flags = if enemy & bit(to) != 0 { Move::CAPTURE } else { Move::QUIET };
+            //Pawns reaching the last rank have to pick what they turn into
+            if self.piece_type == PieceType::Pawn && (to / 8 == 0 || to / 8 == 7) {
+                moves.extend(PROMOTION_PIECES.map(|promotion| Move::new(square, to, Some(promotion), flags)));
+            }
+            else {
+                moves.push(Move::new(square, to, None, flags));
+            }
+        }
+        Ok(moves)
+    }
+
+    //Same as get_moves but drops every move that would leave our own king attacked (pins, check evasions, walking into check)
+    pub fn get_legal_moves(&self,position: &Position,square: usize) -> Result<Vec<Move>,ChessEngineError> {
+        let moves = self.get_moves(position,square)?;
+        let mut legal_moves: Vec<Move> = vec![];
+        let mut scratch = *position;
+        for chess_move in moves {
+            let undo = scratch.make(chess_move)?;
+            if !is_in_check(&scratch, self.color) {
+                legal_moves.push(chess_move);
+            }
+            scratch.unmake(&undo);
+        }
+        Ok(legal_moves)
+    }
+}
+
+impl Color {
+    pub fn opponent(&self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White
+        }
+    }
+}
+
+
+pub fn find_king(position: &Position,color: Color) -> Option<usize> {
+    squares(position.pieces[color as usize][PieceType::King as usize]).next()
+}
+
+//Looks outwards from the square with each piece's attack pattern, anything of that kind found there attacks the square
+pub fn is_square_attacked(position: &Position,square: usize,by_color: Color) -> bool {
+    let pieces = &position.pieces[by_color as usize];
+    let occupied = position.occupancy[0] | position.occupancy[1];
+
+    pawn_attacks(by_color.opponent(), square) & pieces[PieceType::Pawn as usize] != 0
+        || knight_attacks(square) & pieces[PieceType::Knight as usize] != 0
+        || king_attacks(square) & pieces[PieceType::King as usize] != 0
+        || rook_attacks(square, occupied) & (pieces[PieceType::Rook as usize] | pieces[PieceType::Queen as usize]) != 0
+        || bishop_attacks(square, occupied) & (pieces[PieceType::Bishop as usize] | pieces[PieceType::Queen as usize]) != 0
+}
+
+pub fn is_in_check(position: &Position,color: Color) -> bool {
+    match find_king(position, color) {
+        Some(king_position) => is_square_attacked(position, king_position, color.opponent()),
+        None => false
+    }
+}
+
+//Material from white's point of view, kings cancel each other out
+fn material_balance(position: &Position) -> f32 {
+    let mut balance = 0.0;
+    for piece_type in [PieceType::Pawn,PieceType::Knight,PieceType::Bishop,PieceType::Rook,PieceType::Queen,PieceType::King] {
+        let value = Piece::new(Color::White, piece_type).value as f32;
+        let white_count = position.pieces[Color::White as usize][piece_type as usize].count_ones() as f32;
+        let black_count = position.pieces[Color::Black as usize][piece_type as usize].count_ones() as f32;
+        balance += value * (white_count - black_count);
+    }
+    balance
+}
+
+//Squares the knights, bishops, rooks and queens of one side can go to, a rough measure of how active they are
+fn mobility(position: &Position,color: Color) -> u32 {
+    let own = position.occupancy[color as usize];
+    let occupied = position.occupancy[0] | position.occupancy[1];
+    let pieces = &position.pieces[color as usize];
+    let reach = |bitboard: u64,attacks: &dyn Fn(usize) -> u64| squares(bitboard).map(|square| (attacks(square) & !own).count_ones()).sum::<u32>();
+
+    reach(pieces[PieceType::Knight as usize], &knight_attacks)
+        + reach(pieces[PieceType::Bishop as usize] | pieces[PieceType::Queen as usize], &|square| bishop_attacks(square, occupied))
+        + reach(pieces[PieceType::Rook as usize] | pieces[PieceType::Queen as usize], &|square| rook_attacks(square, occupied))
+}
+
+//Material and mobility from the side to move's point of view, what a quiet position is worth without searching
+fn evaluate(position: &Position) -> f32 {
+    let score = material_balance(position) + MOBILITY_WEIGHT * (mobility(position, Color::White) as f32 - mobility(position, Color::Black) as f32);
+    match position.whos_move {
+        Color::White => score,
+        Color::Black => -score
+    }
+}
+
+//Turns a square name like "e3" into a board index, a8 is 0 and h1 is 63
+pub fn parse_square(square: &str) -> Option<usize> {
+    let mut chars = square.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+    let col = file as usize - 'a' as usize;
+    let row = 7 - (rank as usize - '1' as usize);
+    Some(row * 8 + col)
+}
+
+pub const MATE_SCORE: f32 = 500.0;
+//How many nodes go by between looks at the clock, reading it is not free
+const NODES_BETWEEN_CLOCK_CHECKS: u64 = 1024;
+pub const DEFAULT_TABLE_MB: usize = 16;
+//The most a GUI can ask the table to take up
+pub const MAX_TABLE_MB: usize = 1024;
+pub const ENGINE_NAME: &str = "Rust Chess Engine";
+//How deep a search goes with no depth or time given, and the deepest it will go when searching on the clock
+pub const DEFAULT_DEPTH: u8 = 5;
+pub const MAX_DEPTH: u8 = 64;
+//Time kept back on every move for the GUI to receive it
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+//How many moves are assumed to be left when the time control does not say
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+//Inside the search scores are from the side to move's point of view, search turns them round to white's for everyone else
+type SearchResult = (Option<Move>,f32);
+//Small enough that no two different evaluations fit inside it
+const NULL_WINDOW: f32 = 0.01;
+const MOBILITY_WEIGHT: f32 = 0.04;
+
+//Lets whoever started a search stop it from outside and see how much work it did,
+//the deadline is only read when one is set because wasm has no clock behind Instant
+pub struct SearchControl<'a> {
+    stop: &'a AtomicBool,
+    deadline: Option<Instant>,
+    iteration: u8,
+    aborted: bool,
+    //Keys of the positions before the one being searched, the game's and then the search's own path
+    history: Vec<u64>,
+    pub nodes: u64
+}
+
+impl<'a> SearchControl<'a> {
+    pub fn new(stop: &'a AtomicBool,deadline: Option<Instant>) -> SearchControl<'a> {
+        SearchControl { stop, deadline, iteration: 0, aborted: false, history: Vec::new(), nodes: 0 }
+    }
+
+    //Positions the game has already been through, oldest first, so the search can see repetitions
+    pub fn with_history(mut self,history: Vec<u64>) -> SearchControl<'a> {
+        self.history = history;
+        self
+    }
+
+    //A mate on the move that reaches the fifty-move rule still counts, the same as in get_game_result
+    fn is_draw(&self,position: &Position) -> bool {
+        position.is_insufficient_material() || count_repetitions(position, &self.history) > 0
+            || (position.halfmove_clock >= 100 && !(position.is_check() && position.legal_moves().is_empty()))
+    }
+
+    //The first iteration always finishes so there is a move to play
+    fn should_stop(&mut self) -> bool {
+        if self.iteration > 1 && !self.aborted {
+            let out_of_time = self.nodes.is_multiple_of(NODES_BETWEEN_CLOCK_CHECKS) && self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            self.aborted = out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        self.aborted
+    }
+}
+
+//Moves to the mate for a mate score, negative when getting mated. A mate score is MATE_SCORE less the plies to the mate,
+//the pv can be cut short so it is no help
+pub fn mate_distance(score: f32) -> Option<i32> {
+    if score.abs() <= MATE_SCORE - 100.0 {
+        return None;
+    }
+    let moves = ((MATE_SCORE - score.abs()).round() as i32 + 1) / 2;
+    Some(if score > 0.0 { moves } else { -moves })
+}
+
+//A fixed move time is used as given, a clock is shared out over the moves that are left
+pub fn time_budget(move_time: Option<Duration>,clock: Option<Duration>,increment: Duration,moves_to_go: Option<u32>) -> Option<Duration> {
+    if let Some(move_time) = move_time {
+        return Some(move_time.saturating_sub(MOVE_OVERHEAD).max(Duration::from_millis(1)));
+    }
+    let clock = clock?;
+    let share = clock / moves_to_go.filter(|moves| *moves > 0).unwrap_or(DEFAULT_MOVES_TO_GO) + increment * 3 / 4;
+    Some(share.min(clock.saturating_sub(MOVE_OVERHEAD)).max(Duration::from_millis(1)))
+}
+
+//What a finished iteration found, pv is the line the engine expects to be played
+#[derive(Clone,PartialEq,Debug)]
+pub struct SearchInfo {
+    pub depth: u8,
+    pub score: f32,
+    pub nodes: u64,
+    //Kept apart from the pv, which is read back from the table and can come out short or empty
+    pub best_move: Option<Move>,
+    pub pv: Vec<Move>
+}
+
+//A SearchInfo to send to another thread or process, the moves are in coordinate notation
+#[derive(Clone,PartialEq,Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchProgress {
+    pub depth: u8,
+    pub score: f32,
+    pub nodes: u64,
+    pub best_move: Option<String>,
+    pub pv: Vec<String>
+}
+
+impl From<&SearchInfo> for SearchProgress {
+    fn from(info: &SearchInfo) -> SearchProgress {
+        SearchProgress {
+            depth: info.depth,
+            score: info.score,
+            nodes: info.nodes,
+            best_move: info.best_move.map(|best_move| best_move.to_string()),
+            pv: info.pv.iter().map(|chess_move| chess_move.to_string()).collect()
+        }
+    }
+}
+
+//Fail-soft, so a score outside the window still says which side of it the real one lies.
+//The first move is searched with the full window and the rest only have to show they are no better, the few that are get searched again
+fn negamax(position: &mut Position,depth: u8,ply: u8,mut alpha: f32,beta: f32,transposition_table: &mut TranspositionTable,control: &mut SearchControl) -> SearchResult {
+    if depth == 0 {
+        return (None,quiescence(position,ply,alpha,beta,control));
+    }
+
+    control.nodes += 1;
+    if control.should_stop() {
+        return (None,0.0);
+    }
+    //Repeating a position counts as a draw straight away, if it was worth repeating once it is worth repeating again
+    if ply > 0 && control.is_draw(position) {
+        return (None,0.0);
+    }
+
+    //The root always searches so it has a move to hand back
+    let key = position.key();
+    let entry = transposition_table.probe(key);
+    if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+        let score = score_from_table(entry.score,ply);
+        let usable = match entry.bound {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha
+        };
+        if usable {
+            return (entry.best_move,score);
+        }
+    }
+
+    let mut moves = position.legal_moves();
+    if moves.is_empty() {
+        return (None,if position.is_check() { mated_score(ply) } else { 0.0 });
+    }
+    order_moves(position,&mut moves,entry.and_then(|entry| entry.best_move));
+
+    let alpha_before = alpha;
+    let mut best_score = f32::NEG_INFINITY;
+    let mut best_move = None;
+    for (index,chess_move) in moves.into_iter().enumerate() {
+        let undo = position.make(chess_move).expect("legal moves can always be played");
+        control.history.push(key);
+        let mut score = if index == 0 {
+            -negamax(position,depth - 1,ply + 1,-beta,-alpha,transposition_table,control).1
+        }
+        else {
+            -negamax(position,depth - 1,ply + 1,-alpha - NULL_WINDOW,-alpha,transposition_table,control).1
+        };
+        if index > 0 && score > alpha && score < beta {
+            score = -negamax(position,depth - 1,ply + 1,-beta,-alpha,transposition_table,control).1;
+        }
+        position.unmake(&undo);
+        control.history.pop();
+
+        if score > best_score {
+            best_score = score;
+            best_move = Some(chess_move);
+        }
+        if score > alpha {
+            alpha = score;
+        }
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    //A stopped search only has guesses, they must not end up in the table
+    if !control.aborted {
+        let bound = if best_score <= alpha_before {
+            Bound::Upper
+        }
+        else if best_score >= beta {
+            Bound::Lower
+        }
+        else {
+            Bound::Exact
+        };
+        //When every move failed low none of them is known to be best, the table keeps whatever move it had
+        let table_move = if bound == Bound::Upper { None } else { best_move };
+        transposition_table.store(key, depth, bound, score_to_table(best_score,ply), table_move);
+    }
+
+    (best_move,best_score)
+}
+
+//Plays out captures and promotions until nothing is hanging, so a line never ends halfway through an exchange.
+//The side to move can settle for the evaluation instead of capturing, unless it is in check and has to get out of it
+fn quiescence(position: &mut Position,ply: u8,mut alpha: f32,beta: f32,control: &mut SearchControl) -> f32 {
+    control.nodes += 1;
+    if control.should_stop() {
+        return 0.0;
+    }
+    if ply > 0 && control.is_draw(position) {
+        return 0.0;
+    }
+
+    let in_check = position.is_check();
+    let mut best_score = f32::NEG_INFINITY;
+    if !in_check {
+        best_score = evaluate(position);
+        if best_score >= beta {
+            return best_score;
+        }
+        alpha = alpha.max(best_score);
+    }
+
+    let mut moves = position.legal_moves();
+    if moves.is_empty() {
+        return if in_check { mated_score(ply) } else { 0.0 };
+    }
+    if !in_check {
+        moves.retain(|chess_move| chess_move.is_capture() || chess_move.promotion.is_some());
+    }
+    order_moves(position,&mut moves,None);
+
+    let key = position.key();
+    for chess_move in moves {
+        let undo = position.make(chess_move).expect("legal moves can always be played");
+        control.history.push(key);
+        let score = -quiescence(position,ply + 1,-beta,-alpha,control);
+        position.unmake(&undo);
+        control.history.pop();
+
+        best_score = best_score.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best_score
+}
+
+//Closer mates score higher so the engine takes the fastest one and puts off being mated the longest
+fn mated_score(ply: u8) -> f32 {
+    ply as f32 - MATE_SCORE
+}
+
+//The table's move goes first, then captures and promotions by the most gained and among those the cheapest piece first,
+//then the quiet moves as generated
+fn order_moves(position: &Position,moves: &mut [Move],table_move: Option<Move>) {
+    let value = |piece: Option<Piece>| piece.map_or(0, |piece| piece.value as i32);
+    moves.sort_by_cached_key(|chess_move| {
+        if Some(*chess_move) == table_move {
+            return (0,0,0);
+        }
+        let captured = if chess_move.is_en_passant() { 1 } else { value(position.piece_at(chess_move.to as usize)) };
+        let promoted = value(chess_move.promotion.map(|promotion| Piece::new(position.whos_move, promotion)));
+        if captured + promoted == 0 {
+            return (2,0,0);
+        }
+        (1,-(captured + promoted),value(position.piece_at(chess_move.from as usize)))
+    });
+}
+
+//Mate scores count plies from the root, the table keeps them counted from the position itself so they fit any path there
+fn score_to_table(score: f32,ply: u8) -> f32 {
+    if score > MATE_SCORE / 2.0 { score + ply as f32 }
+    else if score < -MATE_SCORE / 2.0 { score - ply as f32 }
+    else { score }
+}
+
+fn score_from_table(score: f32,ply: u8) -> f32 {
+    if score > MATE_SCORE / 2.0 { score - ply as f32 }
+    else if score < -MATE_SCORE / 2.0 { score + ply as f32 }
+    else { score }
+}
+
+//Returns None as the move only when there is nothing to play
+pub fn calculate_with_iterative_deepening(position: &Position,recursion_level: u8) -> (Option<Move>,f32) {
+    let stop = AtomicBool::new(false);
+    search(position,recursion_level,&mut TranspositionTable::new(DEFAULT_TABLE_MB),&mut SearchControl::new(&stop,None),&mut |_| ())
+}
+
+//Deepens one ply at a time until max_depth or until told to stop, an unfinished iteration is thrown away.
+//The table is kept by the caller so what one search learnt helps the next
+pub fn search(position: &Position,max_depth: u8,transposition_table: &mut TranspositionTable,control: &mut SearchControl,on_iteration: &mut dyn FnMut(&SearchInfo)) -> (Option<Move>,f32) {
+    //The search plays and takes back moves on its own copy
+    let mut position = *position;
+
+    let sign = match position.whos_move {
+        Color::White => 1.0,
+        Color::Black => -1.0
+    };
+    let mut result = (None,0.0);
+    transposition_table.new_search();
+    //The root's move from the last iteration is in the table, so every iteration starts with it
+    for i in 1..=max_depth {
+        control.iteration = i;
+        let (best_move,score) = negamax(&mut position,i,0,f32::NEG_INFINITY,f32::INFINITY,transposition_table,control);
+        if control.aborted {
+            break;
+        }
+
+        let best_score = sign * score;
+        result = (best_move,best_score);
+        on_iteration(&SearchInfo { depth: i, score: best_score, nodes: control.nodes, best_move, pv: principal_variation(&position,i,transposition_table) });
+
+        if best_move.is_none() {
+            break;
+        }
+    }
+
+    result
+}
+
+//Follows the best moves the table kept from the root on
+fn principal_variation(position: &Position,depth: u8,transposition_table: &TranspositionTable) -> Vec<Move> {
+    let mut position = *position;
+    let mut pv = Vec::new();
+    for _ in 0..depth {
+        //Entries can come from other searches or other positions with the same bucket, so only legal moves count
+        let Some(chess_move) = transposition_table.probe(position.key()).and_then(|entry| entry.best_move) else { break };
+        if !position.legal_moves().contains(&chess_move) {
+            break;
+        }
+        position.make(chess_move).expect("legal moves can always be played");
+        pv.push(chess_move);
+    }
+    pv
+}
+
+//How often the position was already on the board, only positions since the last capture or pawn move can match
+pub fn count_repetitions(position: &Position,history: &[u64]) -> usize {
+    let key = position.key();
+    history.iter().rev().take(position.halfmove_clock as usize).filter(|earlier| **earlier == key).count()
+}
+
+//History holds the keys of the positions the game went through before this one, oldest first.
+//Threefold repetition and the fifty-move rule are declared at once rather than waiting for a claim
+pub fn get_game_result(position: &Position,history: &[u64]) -> GameResult {
+    if position.legal_moves().is_empty() {
+        return if position.is_check() {
+            match position.whos_move {
+                Color::White => GameResult::Checkmate(Color::Black),
+                Color::Black => GameResult::Checkmate(Color::White)
+            }
+        }
+        else {
+            GameResult::Stalemate
+        };
+    }
+
+    let occurrences = count_repetitions(position, history) + 1;
+    if position.is_insufficient_material() {
+        GameResult::Draw(DrawReason::InsufficientMaterial)
+    }
+    else if occurrences >= 5 {
+        GameResult::Draw(DrawReason::FivefoldRepetition)
+    }
+    else if position.halfmove_clock >= 150 {
+        GameResult::Draw(DrawReason::SeventyFiveMoveRule)
+    }
+    else if occurrences >= 3 {
+        GameResult::Draw(DrawReason::ThreefoldRepetition)
+    }
+    else if position.halfmove_clock >= 100 {
+        GameResult::Draw(DrawReason::FiftyMoveRule)
+    }
+    else {
+        GameResult::Ongoing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{bitboard::{bishop_attacks,bit,king_attacks,knight_attacks,pawn_attacks,rook_attacks,squares},calculate_with_iterative_deepening,find_king,search,SearchControl,get_game_result,count_repetitions,DrawReason,perft::{divide,perft},pgn::{read_games,Game,PgnError},is_in_check,parse_square,chess_move::{square_name,Move,UciMoveError},position::{FenError,Position,START_FEN},san::{move_to_san,parse_san,SanError},CastlingRights,Color,GameResult,PieceType,transposition_table::{Bound,TranspositionTable},order_moves,quiescence,mate_distance,time_budget,MATE_SCORE};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    fn move_names(moves: &[Move]) -> Vec<String> {
+        let mut names: Vec<String> = moves.iter().map(|chess_move| chess_move.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_simple_take() {
+        let position = Position::from_fen("rnb1kbnr/pppppppp/5q2/8/4N3/8/PPPPPPPP/R1BQKBNR").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,4);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Nxf6+");
+    }
+
+    #[test]
+    fn test_scholar() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/2B5/4PQ2/PPPP1PPP/RNB1K1NR").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,4);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Bxf7#");
+    }
+
+    #[test]
+    fn test_back_rank() {
+        let position = Position::from_fen("6k1/5ppp/8/8/8/8/8/1Q2K3").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Qb8#");
+    }
+
+    #[test]
+    fn test_fork() {
+        let position = Position::from_fen("2r3k1/5ppp/8/3N4/8/8/8/4K3").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Ne7+");
+    }
+
+    #[test]
+    fn test_smother() {
+        let position = Position::from_fen("6rk/6pp/8/4N3/8/8/B7/4K3").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,4);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Nf7#");
+    }
+
+    #[test]
+    fn test_two_move_1() {
+        let position = Position::from_fen("2r4k/6pp/8/4N3/8/1Q6/B7/4K3").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,5);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Ng6+");
+    }
+
+    #[test]
+    fn test_two_move_2() {
+        let position = Position::from_fen("r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,5);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Qh6+");
+    }
+
+    #[test]
+    fn test_three_move_1() {
+        let position = Position::from_fen("2r3k1/p4p2/3Rp2p/1p2P1pK/8/1P4P1/P3Q2P/1q6 b").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,5);
+        assert_eq!(best_move.unwrap().to_string(), "b1g6");
+    }
+
+    #[test]
+    fn test_queen_sacrifice_smother() {
+        let position = Position::from_fen("5r1k/6pp/7N/8/8/1Q6/8/6K1 w").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Qg8+");
+    }
+
+    #[test]
+    fn test_black_back_rank() {
+        let position = Position::from_fen("r5k1/8/8/8/8/8/5PPP/6K1 b").unwrap();
+        let (best_move,best_score) = calculate_with_iterative_deepening(&position,2);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Ra1#");
+        assert_eq!(best_score, -(MATE_SCORE - 1.0));
+    }
+
+    #[test]
+    fn test_skewer() {
+        let position = Position::from_fen("8/8/8/q3k3/7R/8/8/7K w").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Rh5+");
+    }
+
+    #[test]
+    fn test_rook_roller_mate_in_two() {
+        let position = Position::from_fen("6k1/8/8/8/8/8/R7/1R4K1 w").unwrap();
+        let (best_move,best_score) = calculate_with_iterative_deepening(&position,4);
+        assert_eq!(best_score, MATE_SCORE - 3.0);
+        assert!(["Ra7","Rb7"].contains(&move_to_san(&position,best_move.unwrap()).as_str()));
+    }
+
+    #[test]
+    fn captures_are_ordered_by_victim_first() {
+        let position = Position::from_fen("4k3/8/8/2p5/NP6/8/3r4/4K3 w").unwrap();
+        let mut moves = position.legal_moves();
+        order_moves(&position,&mut moves,None);
+        let first: Vec<String> = moves[..3].iter().map(|chess_move| move_to_san(&position,*chess_move)).collect();
+        assert_eq!(first, vec!["Kxd2","bxc5","Nxc5"]);
+
+        let quiet = Move::from_uci(&position,"e1f1").unwrap();
+        order_moves(&position,&mut moves,Some(quiet));
+        assert_eq!(moves[0], quiet);
+    }
+
+    #[test]
+    fn quiescence_sees_the_recapture() {
+        let position = Position::from_fen("4k3/8/4p3/3p4/8/8/8/3QK3 w").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,1);
+        assert_ne!(move_to_san(&position,best_move.unwrap()), "Qxd5");
+    }
+
+    //The same tree without any pruning, alpha-beta has to land on exactly the score this does.
+    //Captures at the leaves are left to quiescence with a window that cuts nothing off
+    fn minimax(position: &Position,depth: u8,ply: u8) -> f32 {
+        let stop = AtomicBool::new(false);
+        if depth == 0 {
+            return quiescence(&mut position.clone(),ply,f32::NEG_INFINITY,f32::INFINITY,&mut SearchControl::new(&stop,None));
+        }
+        if ply > 0 && (position.halfmove_clock >= 100 || position.is_insufficient_material()) {
+            return 0.0;
+        }
+        let moves = position.legal_moves();
+        if moves.is_empty() {
+            return if position.is_check() { ply as f32 - MATE_SCORE } else { 0.0 };
+        }
+        moves.into_iter().map(|chess_move| -minimax(&position.make_move(chess_move).unwrap(),depth - 1,ply + 1)).fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    #[test]
+    fn alpha_beta_matches_minimax() {
+        for (fen,depth) in [(START_FEN,3),(KIWIPETE,1),("2r3k1/p4p2/3Rp2p/1p2P1pK/8/1P4P1/P3Q2P/1q6 b",2),("r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w",2)] {
+            let position = Position::from_fen(fen).unwrap();
+            let sign = if position.whos_move == Color::White { 1.0 } else { -1.0 };
+            let stop = AtomicBool::new(false);
+            let (_,best_score) = search(&position,depth,&mut TranspositionTable::new(1),&mut SearchControl::new(&stop,None),&mut |_| ());
+            assert_eq!(best_score, sign * minimax(&position,depth,0), "{fen}");
+        }
+    }
+
+    #[test]
+    fn search_reports_every_iteration() {
+        let position = Position::from_fen("6k1/5ppp/8/8/8/8/8/1Q2K3").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut control = SearchControl::new(&stop,None);
+        let mut infos = Vec::new();
+        let (best_move,_) = search(&position,3,&mut TranspositionTable::new(1),&mut control,&mut |info| infos.push(info.clone()));
+        assert_eq!(infos.iter().map(|info| info.depth).collect::<Vec<u8>>(), vec![1,2,3]);
+        assert!(infos.windows(2).all(|pair| pair[0].nodes < pair[1].nodes));
+        assert_eq!(infos[2].nodes, control.nodes);
+        assert_eq!(infos[2].pv.first().copied(), best_move);
+        assert_eq!(infos[2].best_move, best_move);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Qb8#");
+    }
+
+    #[test]
+    fn searches_deeper_than_nine_plies() {
+        let position = Position::from_fen("8/8/8/4k3/8/8/4P3/4K3 w").unwrap();
+        let stop = AtomicBool::new(false);
+        let mut depths = Vec::new();
+        let (best_move,_) = search(&position,12,&mut TranspositionTable::new(1),&mut SearchControl::new(&stop,None),&mut |info| depths.push(info.depth));
+        assert_eq!(depths, (1..=12).collect::<Vec<u8>>());
+        assert!(position.legal_moves().contains(&best_move.unwrap()));
+    }
+
+    #[test]
+    fn table_keeps_and_replaces_entries() {
+        let mut table = TranspositionTable::new(1);
+        let chess_move = Some(Move::new(52, 36, None, Move::DOUBLE_PUSH));
+        //Same low bits, so all of these share a bucket
+        let key = |n: u64| (n << 32) | 0x1234;
+        table.store(key(1), 5, Bound::Exact, 0.5, chess_move);
+        assert_eq!(table.probe(key(1)).map(|entry| (entry.depth,entry.bound,entry.score,entry.best_move)), Some((5,Bound::Exact,0.5,chess_move)));
+        assert_eq!(table.probe(key(2)), None);
+
+        table.store(key(1), 6, Bound::Lower, 1.5, None);
+        assert_eq!(table.probe(key(1)).map(|entry| (entry.depth,entry.bound,entry.best_move)), Some((6,Bound::Lower,chess_move)));
+
+        for (n,depth) in [(2,3),(3,7),(4,4)] {
+            table.store(key(n), depth, Bound::Upper, 0.0, None);
+        }
+        table.store(key(5), 1, Bound::Exact, 0.0, None);
+        assert_eq!(table.probe(key(2)), None);
+        assert!([1,3,4,5].iter().all(|n| table.probe(key(*n)).is_some()));
+
+        //After a new search the old entries go first, however deep they were
+        table.new_search();
+        table.store(key(6), 1, Bound::Exact, 0.0, None);
+        assert!(table.probe(key(6)).is_some());
+        assert_eq!([1,3,4,5].iter().filter(|n| table.probe(key(**n)).is_some()).count(), 3);
+
+        table.clear();
+        assert_eq!(table.probe(key(6)), None);
+    }
+
+    #[test]
+    fn table_carries_over_between_searches() {
+        let position = Position::from_fen(KIWIPETE).unwrap();
+        let stop = AtomicBool::new(false);
+        let mut table = TranspositionTable::new(1);
+        let mut first = SearchControl::new(&stop,None);
+        let (first_move,_) = search(&position,3,&mut table,&mut first,&mut |_| ());
+        let mut second = SearchControl::new(&stop,None);
+        let (second_move,_) = search(&position,3,&mut table,&mut second,&mut |_| ());
+        assert!(second.nodes < first.nodes / 2, "{} then {}", first.nodes, second.nodes);
+        assert_eq!(first_move, second_move);
+    }
+
+    #[test]
+    fn stopped_search_still_finishes_the_first_iteration() {
+        let position = Position::from_fen(KIWIPETE).unwrap();
+        let stop = AtomicBool::new(true);
+        let mut control = SearchControl::new(&stop,None);
+        let mut depths = Vec::new();
+        let (best_move,_) = search(&position,6,&mut TranspositionTable::new(1),&mut control,&mut |info| depths.push(info.depth));
+        assert_eq!(depths, vec![1]);
+        assert!(position.legal_moves().contains(&best_move.unwrap()));
+    }
+
+    #[test]
+    fn knight_correct_restrictions() {
+        let position = Position::from_fen("rnbqkb1r/ppppp1p1/5p1p/8/n5N1/8/PPPPPPPP/RNBQKB1R").unwrap();
+        let board = position.board();
+        for (i,piece) in board.iter().enumerate() {
+            if i == 32 {
+                let piece = match piece {
+                    Some(piece) => piece,
+                    None => continue
+                };
+                
+                assert!(!piece.get_moves(&position,i).unwrap().iter().any(|chess_move| chess_move.to == 38));
+            }
+        }
+    }
+
+    #[test]
+    fn pinned_piece_cannot_move() {
+        let position = Position::from_fen("4r1k1/8/8/8/8/8/4N3/4K3").unwrap();
+        let board = position.board();
+        let knight = board[52].as_ref().unwrap();
+        assert!(!knight.get_moves(&position,52).unwrap().is_empty());
+        assert!(knight.get_legal_moves(&position,52).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_must_be_answered() {
+        let position = Position::from_fen("3r2k1/8/8/8/8/8/R7/3K4").unwrap();
+        let board = position.board();
+        assert!(is_in_check(&position, Color::White));
+
+        let king_moves = board[59].as_ref().unwrap().get_legal_moves(&position,59).unwrap();
+        assert_eq!(move_names(&king_moves), vec!["d1c1","d1c2","d1e1","d1e2"]);
+
+        let rook_moves = board[48].as_ref().unwrap().get_legal_moves(&position,48).unwrap();
+        assert_eq!(move_names(&rook_moves), vec!["a2d2"]);
+        assert!(rook_moves[0].flags == Move::QUIET);
+    }
+
+    #[test]
+    fn king_cannot_capture_protected_piece() {
+        let position = Position::from_fen("3r2k1/8/8/8/8/8/3q4/3K4").unwrap();
+        let board = position.board();
+        let king = board[59].as_ref().unwrap();
+        assert!(king.get_moves(&position,59).unwrap().contains(&Move::new(59, 51, None, Move::CAPTURE)));
+        assert!(king.get_legal_moves(&position,59).unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_checkmate() {
+        let position = Position::from_fen("1Q4k1/5ppp/8/8/8/8/8/4K3 b").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn detects_stalemate() {
+        let position = Position::from_fen("k7/2Q5/1K6/8/8/8/8/8 b").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Stalemate);
+    }
+
+    #[test]
+    fn detects_ongoing_game() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Ongoing);
+    }
+
+    #[test]
+    fn detects_insufficient_material() {
+        for fen in ["8/8/4k3/8/8/3K4/8/8 w", "8/8/4k3/8/8/3KB3/8/8 w", "8/8/4k3/8/8/3KN3/8/8 b", "8/2b5/4k3/8/8/3KB3/8/8 w", "8/2b5/4k3/8/8/3KB3/8/6B1 w"] {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(get_game_result(&position, &[]), GameResult::Draw(DrawReason::InsufficientMaterial), "{fen}");
+        }
+        for fen in ["8/3b4/4k3/8/8/3KB3/8/8 w", "B7/2b5/4k3/8/8/3KB3/8/8 w", "8/8/4k3/8/8/3KNN2/8/8 w", "8/8/4k3/8/8/3KP3/8/8 w", "8/8/4k3/8/8/3KR3/8/8 w"] {
+            let position = Position::from_fen(fen).unwrap();
+            assert!(!position.is_insufficient_material(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn detects_repetitions() {
+        let start = Position::from_fen(START_FEN).unwrap();
+        let knight_dance = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let (position,history) = start.play_uci_moves(&knight_dance.repeat(2)).unwrap();
+        assert_eq!(count_repetitions(&position, &history), 2);
+        assert_eq!(get_game_result(&position, &history), GameResult::Draw(DrawReason::ThreefoldRepetition));
+        assert_eq!(get_game_result(&position, &history[1..]), GameResult::Ongoing);
+
+        let (position,history) = start.play_uci_moves(&knight_dance.repeat(4)).unwrap();
+        assert_eq!(get_game_result(&position, &history), GameResult::Draw(DrawReason::FivefoldRepetition));
+    }
+
+    #[test]
+    fn repetitions_need_the_same_side_to_move() {
+        let start = Position::from_fen(START_FEN).unwrap();
+        let (position,history) = start.play_uci_moves(&["g1f3", "g8f6", "f3g1", "f6g8", "b1c3", "b8c6", "c3b1", "c6b8"]).unwrap();
+        assert_eq!(count_repetitions(&position, &history), 2);
+        let (position,history) = start.play_uci_moves(&["g1f3", "g8f6", "f3g1", "f6g8", "g1f3"]).unwrap();
+        assert_eq!(count_repetitions(&position, &history), 1);
+        let (_,other_history) = start.play_uci_moves(&["g1f3", "g8f6", "f3g5", "f6g4", "g5f3", "g4f6", "f3g1"]).unwrap();
+        assert_eq!(count_repetitions(&position, &other_history), 0);
+    }
+
+    #[test]
+    fn detects_move_rules() {
+        let position = Position::from_fen("8/8/4k3/8/8/3KR3/8/8 w - - 100 80").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Draw(DrawReason::FiftyMoveRule));
+        let position = Position::from_fen("8/8/4k3/8/8/3KR3/8/8 w - - 150 105").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Draw(DrawReason::SeventyFiveMoveRule));
+        let position = Position::from_fen("1Q4k1/5ppp/8/8/8/8/8/4K3 b - - 100 80").unwrap();
+        assert_eq!(get_game_result(&position, &[]), GameResult::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn resets_the_fifty_move_count_when_ahead() {
+        let position = Position::from_fen("k7/8/8/8/8/8/P7/1Q4K1 w - - 99 80").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(square_name(best_move.unwrap().from as usize), "a2");
+    }
+
+    #[test]
+    fn mate_distance_counts_moves() {
+        assert_eq!(mate_distance(MATE_SCORE - 1.0), Some(1));
+        assert_eq!(mate_distance(MATE_SCORE - 5.0), Some(3));
+        assert_eq!(mate_distance(-(MATE_SCORE - 4.0)), Some(-2));
+        assert_eq!(mate_distance(12.5), None);
+    }
+
+    #[test]
+    fn time_budget_shares_out_the_clock() {
+        let millis = Duration::from_millis;
+        assert_eq!(time_budget(Some(millis(1000)), Some(millis(5)), Duration::ZERO, None), Some(millis(950)));
+        assert_eq!(time_budget(Some(millis(10)), None, Duration::ZERO, None), Some(millis(1)));
+        assert_eq!(time_budget(None, Some(millis(60000)), millis(1000), None), Some(millis(2750)));
+        assert_eq!(time_budget(None, Some(millis(20000)), Duration::ZERO, Some(10)), Some(millis(2000)));
+        //Never more than the clock holds
+        assert_eq!(time_budget(None, Some(millis(100)), millis(1000), Some(1)), Some(millis(50)));
+        assert_eq!(time_budget(None, None, millis(1000), Some(1)), None);
+    }
+
+    #[test]
+    fn mates_on_the_fiftieth_move() {
+        let position = Position::from_fen("6k1/5ppp/8/8/8/8/8/1Q2K3 w - - 99 80").unwrap();
+        let (best_move,best_score) = calculate_with_iterative_deepening(&position,2);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "Qb8#");
+        assert_eq!(best_score, MATE_SCORE - 1.0);
+    }
+
+    #[test]
+    fn repeats_a_position_when_behind() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/Q3K3 b - - 10 60").unwrap();
+        let repeated = Move::from_uci(&position,"e8d7").unwrap();
+        let history = vec![position.make_move(repeated).unwrap().key()];
+        let stop = AtomicBool::new(false);
+        let (best_move,best_score) = search(&position,3,&mut TranspositionTable::new(1),&mut SearchControl::new(&stop,None).with_history(history),&mut |_| ());
+        assert_eq!(best_move, Some(repeated));
+        assert_eq!(best_score, 0.0);
+    }
+
+    #[test]
+    fn mates_instead_of_stalemating() {
+        let position = Position::from_fen("k7/8/1K6/8/8/8/8/7Q").unwrap();
+        let (best_move,best_score) = calculate_with_iterative_deepening(&position,2);
+        let new_position = position.make_move(best_move.unwrap()).unwrap();
+        assert_eq!(get_game_result(&new_position, &[]), GameResult::Checkmate(Color::White));
+        assert!(best_score > 400.0);
+    }
+
+    #[test]
+    fn en_passant_from_fen() {
+        let position = Position::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+        let board = position.board();
+        assert_eq!(position.en_passant, parse_square("f6"));
+        assert_eq!(position.en_passant, Some(21));
+
+        let pawn = board[28].as_ref().unwrap();
+        let moves = pawn.get_legal_moves(&position,28).unwrap();
+        assert!(moves.contains(&Move::new(28, 21, None, Move::CAPTURE | Move::EN_PASSANT)));
+        assert!(!moves.iter().any(|chess_move| chess_move.to == 19)); //d5 pushed two squares earlier, the chance to take it is gone
+    }
+
+    #[test]
+    fn en_passant_removes_captured_pawn() {
+        let position = Position::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3").unwrap();
+        let new_position = position.make_move(position.find_move(28, 21, None).unwrap()).unwrap();
+        let new_board = new_position.board();
+        assert!(new_board[29].is_none());
+        assert!(new_board[28].is_none());
+        assert_eq!(new_board[21].unwrap().piece_type, PieceType::Pawn);
+        assert_eq!(new_position.pieces[Color::Black as usize][PieceType::Pawn as usize].count_ones(), 7);
+        assert_eq!(new_position.en_passant, None);
+        assert!(position.en_passant.is_some());
+    }
+
+    #[test]
+    fn double_push_sets_en_passant_square() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let double_push = position.find_move(52, 36, None).unwrap();
+        assert!(double_push.is_double_push());
+        assert_eq!(position.make_move(double_push).unwrap().en_passant, parse_square("e3"));
+        assert_eq!(position.make_move(position.find_move(52, 44, None).unwrap()).unwrap().en_passant, None);
+    }
+
+    #[test]
+    fn en_passant_cannot_expose_king() {
+        let position = Position::from_fen("8/8/8/KPp4r/8/8/8/7k w - c6 0 1").unwrap();
+        let board = position.board();
+        let pawn = board[25].as_ref().unwrap();
+        assert!(pawn.get_moves(&position,25).unwrap().iter().any(|chess_move| chess_move.is_en_passant()));
+        assert_eq!(move_names(&pawn.get_legal_moves(&position,25).unwrap()), vec!["b5b6"]);
+    }
+
+    #[test]
+    fn en_passant_needs_a_pawn_to_take() {
+        for (fen,pawn,en_passant) in [("4k3/8/8/3PN3/8/8/8/4K3 w - - 0 1",27,"e6"), ("4k3/8/8/8/8/8/3PN3/4K3 w - - 0 1",51,"e3")] {
+            let mut position = Position::from_fen(fen).unwrap();
+            position.en_passant = parse_square(en_passant);
+            let moves = position.piece_at(pawn).unwrap().get_moves(&position,pawn).unwrap();
+            assert!(!moves.iter().any(|chess_move| chess_move.is_en_passant()), "{fen}");
+        }
+    }
+
+    #[test]
+    fn key_covers_side_castling_and_en_passant() {
+        let key = |fen: &str| Position::from_fen(fen).unwrap().key();
+        let position = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_ne!(key(position), key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3"));
+        assert_ne!(key(position), key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3"));
+        assert_ne!(key(position), key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kkq f6 0 3"));
+        assert_ne!(key(START_FEN), key("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1"));
+        assert_eq!(key(position), key("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 12 40"));
+        //Nothing can take on e3, so the square makes no difference
+        assert_eq!(key("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"), key("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"));
+        assert_eq!(Position::from_fen(position).unwrap().hashed_en_passant(), parse_square("f6"));
+    }
+
+    #[test]
+    fn keys_match_polyglot() {
+        let start = Position::from_fen(START_FEN).unwrap();
+        let key = |moves: &str| {
+            let moves: Vec<&str> = moves.split_whitespace().collect();
+            start.play_uci_moves(&moves).unwrap().0.key()
+        };
+        assert_eq!(key(""), 0x463b96181691fc9c);
+        assert_eq!(key("e2e4"), 0x823c9b50fd114196);
+        assert_eq!(key("e2e4 d7d5"), 0x0756b94461c50fb0);
+        assert_eq!(key("e2e4 d7d5 e4e5"), 0x662fafb965db29d4);
+        assert_eq!(key("e2e4 d7d5 e4e5 f7f5"), 0x22a48b5a8e47ff78);
+        assert_eq!(key("e2e4 d7d5 e4e5 f7f5 e1e2"), 0x652a607ca3f242c1);
+        assert_eq!(key("e2e4 d7d5 e4e5 f7f5 e1e2 e8f7"), 0x00fdd303c946bdd9);
+        assert_eq!(key("a2a4 b7b5 h2h4 b5b4 c2c4"), 0x3c8123ea7b067637);
+        assert_eq!(key("a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3"), 0x5c3f9b829b279560);
+    }
+
+    #[test]
+    fn transpositions_share_a_key() {
+        let start = Position::from_fen(START_FEN).unwrap();
+        let (position,_) = start.play_uci_moves(&["g1f3", "b8c6", "b1c3"]).unwrap();
+        let (transposed,_) = start.play_uci_moves(&["b1c3", "b8c6", "g1f3"]).unwrap();
+        assert_eq!(position.key(), transposed.key());
+        let (castled,_) = Position::from_fen(KIWIPETE).unwrap().play_uci_moves(&["e1g1", "e8c8"]).unwrap();
+        assert_eq!(castled.key(), Position::from_fen(&castled.to_fen()).unwrap().key());
+    }
+
+    #[test]
+    fn key_follows_make_and_unmake() {
+        let mut rng = StdRng::seed_from_u64(22);
+        for fen in [START_FEN, KIWIPETE, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1"] {
+            let mut position = Position::from_fen(fen).unwrap();
+            let mut undos = Vec::new();
+            for _ in 0..40 {
+                let moves = position.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                undos.push(position.make(moves[rng.gen_range(0..moves.len())]).unwrap());
+                assert_eq!(position.key(), Position::from_fen(&position.to_fen()).unwrap().key(), "{}", position.to_fen());
+            }
+            while let Some(undo) = undos.pop() {
+                position.unmake(&undo);
+            }
+            assert_eq!(position.key(), Position::from_fen(fen).unwrap().key());
+        }
+    }
+
+    #[test]
+    fn pawn_can_promote_to_any_piece() {
+        let position = Position::from_fen("8/P6k/8/8/8/8/8/K7 w").unwrap();
+        let board = position.board();
+        let moves = board[8].as_ref().unwrap().get_legal_moves(&position,8).unwrap();
+        assert_eq!(move_names(&moves), vec!["a7a8b","a7a8n","a7a8q","a7a8r"]);
+        for promotion in [PieceType::Queen,PieceType::Knight,PieceType::Rook,PieceType::Bishop] {
+            let chess_move = Move::new(8, 0, Some(promotion), Move::QUIET);
+            assert!(moves.contains(&chess_move));
+            let new_board = position.make_move(chess_move).unwrap().board();
+            assert_eq!(new_board[0].unwrap().piece_type, promotion);
+            assert_eq!(new_board[0].unwrap().color, Color::White);
+            assert!(new_board[8].is_none());
+        }
+    }
+
+    #[test]
+    fn test_knight_underpromotion_fork() {
+        let position = Position::from_fen("8/3q1P1k/8/8/8/8/8/K7 w").unwrap();
+        let (best_move,_) = calculate_with_iterative_deepening(&position,3);
+        assert_eq!(move_to_san(&position,best_move.unwrap()), "f8=N+");
+    }
+
+    fn castling_moves(fen: &str) -> Vec<i8> {
+        let position = Position::from_fen(fen).unwrap();
+        let king_square = find_king(&position, position.whos_move).unwrap();
+        let king = position.piece_at(king_square).unwrap();
+        let mut moves: Vec<i8> = king.get_legal_moves(&position,king_square).unwrap().iter()
+            .filter(|chess_move| chess_move.is_castle()).map(|chess_move| chess_move.to as i8 - chess_move.from as i8).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn castling_both_sides() {
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1"), vec![-2,2]);
+        assert_eq!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1"), vec![-2,2]);
+
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let castle = position.find_move(60, 62, None).unwrap();
+        assert_eq!(castle.flags, Move::CASTLE);
+        let new_board = position.make_move(castle).unwrap().board();
+        assert_eq!(new_board[62].unwrap().piece_type, PieceType::King);
+        assert_eq!(new_board[61].unwrap().piece_type, PieceType::Rook);
+        assert!(new_board[63].is_none());
+        assert_eq!(position.make_move(castle).unwrap().castling_rights.to_string(), "kq");
+    }
+
+    #[test]
+    fn cannot_castle_out_of_check() {
+        assert!(castling_moves("4k3/4r3/8/8/8/8/8/R3K2R w KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn cannot_castle_through_check() {
+        assert_eq!(castling_moves("4k3/5r2/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![-2]);
+        assert_eq!(castling_moves("4k3/3r4/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![2]);
+    }
+
+    #[test]
+    fn cannot_castle_into_check() {
+        assert_eq!(castling_moves("4k3/6r1/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![-2]);
+        assert_eq!(castling_moves("4k3/2r5/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![2]);
+    }
+
+    #[test]
+    fn queen_side_rook_may_pass_attacked_square() {
+        assert_eq!(castling_moves("4k3/1r6/8/8/8/8/8/R3K2R w KQ - 0 1"), vec![-2,2]);
+    }
+
+    #[test]
+    fn cannot_castle_through_pieces() {
+        assert!(castling_moves("4k3/8/8/8/8/8/8/RN2K1NR w KQ - 0 1").is_empty());
+        assert!(castling_moves("4k3/8/8/8/8/8/8/R2QKB1R w KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn cannot_castle_without_rook() {
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1"), vec![2]);
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/R3K2n w KQ - 0 1"), vec![-2]);
+    }
+
+    #[test]
+    fn cannot_castle_without_rights() {
+        assert!(castling_moves("4k3/8/8/8/8/8/8/R3K2R w - - 0 1").is_empty());
+        assert_eq!(castling_moves("4k3/8/8/8/8/8/8/R3K2R w Q - 0 1"), vec![-2]);
+        assert!(castling_moves("r3k2r/8/8/8/8/8/8/R3K2R b KQ - 0 1").is_empty());
+    }
+
+    #[test]
+    fn cannot_castle_with_moved_rook() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let position = position.make_move(position.find_move(63, 55, None).unwrap()).unwrap();
+        let position = position.make_move(position.find_move(4, 12, None).unwrap()).unwrap();
+        let position = position.make_move(position.find_move(55, 63, None).unwrap()).unwrap();
+        let position = position.make_move(position.find_move(12, 4, None).unwrap()).unwrap();
+        //The rook is back home but the right went away the moment it left
+        assert_eq!(position.castling_rights.to_string(), "Q");
+        let king_moves = position.piece_at(60).unwrap().get_legal_moves(&position,60).unwrap();
+        assert!(!king_moves.iter().any(|chess_move| chess_move.to == 62));
+        assert!(king_moves.contains(&Move::new(60, 58, None, Move::CASTLE)));
+    }
+
+    #[test]
+    fn castling_rights_fen_field() {
+        assert_eq!(CastlingRights::from_fen("KQkq").unwrap().to_string(), "KQkq");
+        assert_eq!(CastlingRights::from_fen("Kq").unwrap().to_string(), "Kq");
+        assert_eq!(CastlingRights::from_fen("-").unwrap(), CastlingRights::default());
+        assert_eq!(CastlingRights::default().to_string(), "-");
+        assert!(CastlingRights::from_fen("KX").is_none());
+
+        let castling_rights = CastlingRights::from_fen("KQkq").unwrap();
+        assert_eq!(castling_rights.update(56, 48).to_string(), "Kkq");
+        assert_eq!(castling_rights.update(30, 7).to_string(), "KQq");
+        assert_eq!(castling_rights.update(4, 12).to_string(), "KQ");
+    }
+
+    #[test]
+    fn position_tracks_clocks_and_side_to_move() {
+        let position = Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!((position.halfmove_clock,position.fullmove_number), (0,1));
+
+        let position = position.make_move(position.find_move(62, 45, None).unwrap()).unwrap();
+        assert_eq!(position.whos_move, Color::Black);
+        assert_eq!((position.halfmove_clock,position.fullmove_number), (1,1));
+
+        let position = position.make_move(position.find_move(12, 28, None).unwrap()).unwrap();
+        assert_eq!(position.whos_move, Color::White);
+        assert_eq!(position.en_passant, parse_square("e6"));
+        assert_eq!((position.halfmove_clock,position.fullmove_number), (0,2));
+        assert_eq!(position.legal_moves().len(), 22);
+
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 12 40").unwrap();
+        assert_eq!((position.halfmove_clock,position.fullmove_number), (12,40));
+        assert!(!position.is_check());
+        assert!(position.make_move(position.find_move(63, 7, None).unwrap()).unwrap().is_check());
+    }
+
+    #[test]
+    fn moves_print_as_coordinates() {
+        assert_eq!(square_name(0), "a8");
+        assert_eq!(square_name(63), "h1");
+        assert_eq!(parse_square(&square_name(36)), Some(36));
+        assert_eq!(Move::new(52, 36, None, Move::DOUBLE_PUSH).to_string(), "e2e4");
+        assert_eq!(Move::new(12, 4, Some(PieceType::Queen), Move::QUIET).to_string(), "e7e8q");
+        assert_eq!(Move::new(60, 62, None, Move::CASTLE).to_string(), "e1g1");
+
+        let chess_move = Move::new(28, 21, None, Move::CAPTURE | Move::EN_PASSANT);
+        assert!(chess_move.is_capture() && chess_move.is_en_passant());
+        assert!(!chess_move.is_castle() && !chess_move.is_double_push());
+    }
+
+    fn square_names(bitboard: u64) -> Vec<String> {
+        let mut names: Vec<String> = squares(bitboard).map(square_name).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn attack_tables() {
+        let square = |name: &str| parse_square(name).unwrap();
+        assert_eq!(square_names(knight_attacks(square("a8"))), vec!["b6","c7"]);
+        assert_eq!(king_attacks(square("e4")).count_ones(), 8);
+        assert_eq!(square_names(pawn_attacks(Color::White, square("a2"))), vec!["b3"]);
+        assert_eq!(square_names(pawn_attacks(Color::Black, square("e7"))), vec!["d6","f6"]);
+
+        //Blockers are included in the attacked set and nothing behind them is
+        let occupied = bit(square("d6")) | bit(square("f4")) | bit(square("d2"));
+        assert_eq!(square_names(rook_attacks(square("d4"), occupied)),
+            vec!["a4","b4","c4","d2","d3","d5","d6","e4","f4"]);
+        assert_eq!(square_names(bishop_attacks(square("c1"), bit(square("e3")))), vec!["a3","b2","d2","e3"]);
+    }
+
+    //Reference node counts from the chessprogramming wiki perft results page
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    fn assert_perft(fen: &str,expected: &[u64]) {
+        let position = Position::from_fen(fen).unwrap();
+        for (depth,nodes) in expected.iter().enumerate() {
+            assert_eq!(perft(&position, depth as u8 + 1), *nodes, "depth {} of {fen}", depth + 1);
+        }
+    }
+
+    #[test]
+    fn perft_start_position() {
+        assert_perft("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20,400,8902,197281]);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(KIWIPETE, &[48,2039,97862]);
+    }
+
+    #[test]
+    fn perft_en_passant_and_pins() {
+        assert_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14,191,2812,43238]);
+    }
+
+    #[test]
+    fn perft_promotions_and_castling() {
+        assert_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6,264,9467]);
+        //Same position with the colors flipped has to give the same numbers
+        assert_perft("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1", &[6,264,9467]);
+    }
+
+    #[test]
+    fn perft_discovered_checks() {
+        assert_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44,1486,62379]);
+    }
+
+    #[test]
+    fn unmake_restores_position() {
+        //Kiwipete has castling, captures and en passant setups, the last one covers promotions with captures
+        for fen in [KIWIPETE,"r3k2r/8/8/3pP3/8/8/8/R3K2R w KQkq d6 5 20","1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1"] {
+            let original = Position::from_fen(fen).unwrap();
+            let mut position = original;
+            for chess_move in original.legal_moves() {
+                let undo = position.make(chess_move).unwrap();
+                assert_eq!(position, original.make_move(chess_move).unwrap());
+                for reply in position.legal_moves() {
+                    let before_reply = position;
+                    let reply_undo = position.make(reply).unwrap();
+                    position.unmake(&reply_undo);
+                    assert_eq!(position, before_reply, "{reply} after {chess_move}");
+                }
+                position.unmake(&undo);
+                assert_eq!(position, original, "{chess_move}");
+            }
+        }
+    }
+
+    #[test]
+    fn divide_adds_up_to_perft() {
+        let position = Position::from_fen(KIWIPETE).unwrap();
+        let divided = divide(&position, 3);
+        assert_eq!(divided.len(), 48);
+        assert_eq!(divided.iter().map(|(_,nodes)| nodes).sum::<u64>(), perft(&position, 3));
+        let castle = divided.iter().find(|(chess_move,_)| chess_move.to_string() == "e1g1").unwrap();
+        assert_eq!(castle.1, 2059);
+    }
+
+    #[test]
+    fn fen_fields_default_when_left_off() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/R3K2R").unwrap();
+        assert_eq!(position.whos_move, Color::White);
+        assert_eq!(position.castling_rights.to_string(), "KQ");
+        assert_eq!(position.en_passant, None);
+        assert_eq!((position.halfmove_clock,position.fullmove_number), (0,1));
+        assert_eq!(Position::from_fen("  4k3/8/8/8/8/8/8/R3K2R   b  ").unwrap().whos_move, Color::Black);
+    }
+
+    #[test]
+    fn bad_fens_are_rejected() {
+        let cases = [
+            ("", FenError::Empty),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1 extra", FenError::TooManyFields(7)),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::BadRankCount(7)),
+            ("4k3/8/8/8/8/8/8/4K2 w - - 0 1", FenError::BadRankLength { rank: 1, squares: 7 }),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", FenError::BadRankLength { rank: 1, squares: 9 }),
+            ("4k3/8/8/8/8/9/8/4K3 w - - 0 1", FenError::BadPieceChar('9')),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::BadPieceChar('X')),
+            ("8/8/8/8/8/8/8/4K3 w - - 0 1", FenError::MissingKing(Color::Black)),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", FenError::TooManyKings(Color::White)),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", FenError::BadSideToMove("x".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w KX - 0 1", FenError::BadCastlingField("KX".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w - e4 0 1", FenError::BadEnPassantSquare("e4".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w - z9 0 1", FenError::BadEnPassantSquare("z9".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w - e3 0 1", FenError::BadEnPassantSquare("e3".to_owned())),
+            ("4k3/8/8/3PN3/8/8/8/4K3 w - e6 0 1", FenError::BadEnPassantSquare("e6".to_owned())),
+            ("4k3/8/8/8/8/8/3PN3/4K3 w - e3 0 1", FenError::BadEnPassantSquare("e3".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w - - -1 1", FenError::BadClocks("-1".to_owned())),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 0", FenError::BadClocks("0".to_owned()))
+        ];
+        for (fen,error) in cases {
+            assert_eq!(Position::from_fen(fen), Err(error), "{fen}");
+        }
+        assert_eq!(Position::from_fen("4k3/8/8/8/8/8/8/4K2 w").unwrap_err().to_string(), "Rank 1 covers 7 squares instead of 8");
+    }
+
+    #[test]
+    fn to_fen_writes_all_fields() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(Position::from_fen(start).unwrap().to_fen(), start);
+        assert_eq!(Position::from_fen(KIWIPETE).unwrap().to_fen(), KIWIPETE);
+        assert_eq!(Position::from_fen("4k3/8/8/8/8/8/8/R3K2R b").unwrap().to_fen(), "4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1");
+
+        let position = Position::from_fen(start).unwrap();
+        let position = position.make_move(position.find_move(52, 36, None).unwrap()).unwrap();
+        assert_eq!(position.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let position = position.make_move(position.find_move(6, 21, None).unwrap()).unwrap();
+        assert_eq!(position.to_fen(), "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2");
+    }
+
+    #[test]
+    fn fen_round_trips_through_random_games() {
+        let mut rng = StdRng::seed_from_u64(2023);
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",KIWIPETE,"r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"] {
+            for _ in 0..20 {
+                let mut position = Position::from_fen(fen).unwrap();
+                for _ in 0..80 {
+                    let moves = position.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    position = position.make_move(moves[rng.gen_range(0..moves.len())]).unwrap();
+                    let fen = position.to_fen();
+                    assert_eq!(Position::from_fen(&fen), Ok(position), "{fen}");
+                }
+            }
+        }
+    }
+
+    fn san_of(fen: &str, from: &str, to: &str, promotion: Option<PieceType>) -> String {
+        let position = Position::from_fen(fen).unwrap();
+        let chess_move = position.find_move(parse_square(from).unwrap(), parse_square(to).unwrap(), promotion).unwrap();
+        move_to_san(&position, chess_move)
+    }
+
+    #[test]
+    fn san_writes_special_moves() {
+        assert_eq!(san_of("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "e2", "e4", None), "e4");
+        assert_eq!(san_of("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", "g1", "f3", None), "Nf3");
+        assert_eq!(san_of("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1", "g1", None), "O-O");
+        assert_eq!(san_of("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1", "c1", None), "O-O-O");
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5", "d6", None), "exd6");
+        assert_eq!(san_of("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "d8", Some(PieceType::Queen)), "exd8=Q+");
+        assert_eq!(san_of("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "d8", Some(PieceType::Knight)), "exd8=N");
+    }
+
+    #[test]
+    fn san_disambiguates() {
+        assert_eq!(san_of("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "b1", "d2", None), "Nbd2");
+        assert_eq!(san_of("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "f3", "d2", None), "Nfd2");
+        assert_eq!(san_of("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1", "a3", None), "R1a3");
+        assert_eq!(san_of("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a5", "a3", None), "R5a3");
+        assert_eq!(san_of("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a1", "b2", None), "Qa1b2");
+        assert_eq!(san_of("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "a3", "b2", None), "Q3b2");
+        assert_eq!(san_of("4k3/8/8/8/8/Q7/8/Q1Q1K3 w - - 0 1", "c1", "b2", None), "Qcb2");
+    }
+
+    #[test]
+    fn san_parses_and_rejects() {
+        let position = Position::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&position, "Nbd2").unwrap().to_string(), "b1d2");
+        assert_eq!(parse_san(&position, "N1d2").unwrap().to_string(), "b1d2");
+        assert_eq!(parse_san(&position, "Nfd2!?").unwrap().to_string(), "f3d2");
+        assert_eq!(parse_san(&position, "Ne9").map_err(|error| error.to_string()), Err("\"Ne9\" is not a move in algebraic notation".to_owned()));
+        assert_eq!(parse_san(&position, "Nd2"), Err(SanError::Ambiguous("Nd2".to_owned())));
+        assert_eq!(parse_san(&position, "Nc6"), Err(SanError::NoMatchingMove("Nc6".to_owned())));
+        assert_eq!(parse_san(&position, "O-O"), Err(SanError::NoMatchingMove("O-O".to_owned())));
+        assert_eq!(parse_san(&position, "Nz9"), Err(SanError::BadSyntax("Nz9".to_owned())));
+        assert_eq!(parse_san(&position, ""), Err(SanError::BadSyntax("".to_owned())));
+
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(parse_san(&position, "0-0-0").unwrap().to_string(), "e1c1");
+        let position = Position::from_fen("3rk3/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san(&position, "exd8Q").unwrap().to_string(), "e7d8q");
+        assert_eq!(parse_san(&position, "e8=R"), Err(SanError::NoMatchingMove("e8=R".to_owned())));
+    }
+
+    #[test]
+    fn san_round_trips_through_random_games() {
+        let mut rng = StdRng::seed_from_u64(2024);
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",KIWIPETE] {
+            for _ in 0..10 {
+                let mut position = Position::from_fen(fen).unwrap();
+                for _ in 0..60 {
+                    let moves = position.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    for chess_move in &moves {
+                        let san = move_to_san(&position, *chess_move);
+                        assert_eq!(parse_san(&position, &san), Ok(*chess_move), "{san} in {}", position.to_fen());
+                    }
+                    position = position.make_move(moves[rng.gen_range(0..moves.len())]).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn uci_moves_parse() {
+        let position = Position::from_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let parse = |text: &str| Move::from_uci(&position, text);
+        assert_eq!(parse("e1g1"), Ok(Move::new(60, 62, None, Move::CASTLE)));
+        assert_eq!(parse("e1c1"), Ok(Move::new(60, 58, None, Move::CASTLE)));
+        assert_eq!(parse("e5d6"), Ok(Move::new(28, 19, None, Move::CAPTURE | Move::EN_PASSANT)));
+        assert_eq!(parse("b7a8n"), Ok(Move::new(9, 0, Some(PieceType::Knight), Move::CAPTURE)));
+        assert_eq!(parse("b7b8q"), Ok(Move::new(9, 1, Some(PieceType::Queen), Move::QUIET)));
+        assert_eq!(parse("b7b8"), Err(UciMoveError::IllegalMove("b7b8".to_owned())));
+        assert_eq!(parse("e1e3"), Err(UciMoveError::IllegalMove("e1e3".to_owned())));
+        assert_eq!(parse("b7b8k"), Err(UciMoveError::BadSyntax("b7b8k".to_owned())));
+        assert_eq!(parse("e9e4"), Err(UciMoveError::BadSyntax("e9e4".to_owned())));
+        assert_eq!(parse("e2"), Err(UciMoveError::BadSyntax("e2".to_owned())));
+        assert_eq!(parse("e1g1é").map_err(|error| error.to_string()), Err("\"e1g1é\" is not a move in coordinate notation".to_owned()));
+    }
+
+    #[test]
+    fn uci_moves_round_trip() {
+        for fen in ["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",KIWIPETE,"r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1","n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1"] {
+            let position = Position::from_fen(fen).unwrap();
+            for chess_move in position.legal_moves() {
+                let new_position = position.make_move(chess_move).unwrap();
+                for reply in new_position.legal_moves() {
+                    assert_eq!(Move::from_uci(&new_position, &reply.to_string()), Ok(reply));
+                }
+                assert_eq!(Move::from_uci(&position, &chess_move.to_string()), Ok(chess_move));
+            }
+        }
+    }
+
+    const OPERA_GAME: &str = r#"[Event "Paris"]
+[Site "Paris FRA"]
+[Date "1858.??.??"]
+[Round "?"]
+[White "Paul Morphy"]
+[Black "Duke Karl / Count Isouard"]
+[Result "1-0"]
+[ECO "C41"]
+
+{Played during a performance of The Barber of Seville.} 1. e4 e5 2. Nf3 d6 3. d4
+Bg4?! {Philidor's Defence, the bishop pin is already dubious.} 4. dxe5 Bxf3
+(4... dxe5 5. Qxd8+ Kxd8 6. Nxe5 $16) 5. Qxf3 dxe5 6. Bc4 Nf6 7. Qb3 Qe7 8. Nc3
+c6 9. Bg5 b5?! 10. Nxb5! cxb5 11. Bxb5+ Nbd7 12. O-O-O Rd8 13. Rxd7 Rxd7 14. Rd1
+Qe6 15. Bxd7+ Nxd7 (15... Qxd7 16. Qb8+ Ke7 17. Qxe5+) 16. Qb8+!! Nxb8 17. Rd8#
+1-0
+"#;
+
+    #[test]
+    fn pgn_replays_the_opera_game() {
+        let game = Game::from_pgn(OPERA_GAME).unwrap();
+        assert_eq!(game.tag("White"), Some("Paul Morphy"));
+        assert_eq!(game.tag("ECO"), Some("C41"));
+        assert_eq!(game.result(), "1-0");
+
+        let mainline = game.mainline();
+        assert_eq!(mainline.len(), 33);
+        let last = game.nodes[*mainline.last().unwrap()].position;
+        assert_eq!(get_game_result(&last, &[]), GameResult::Checkmate(Color::White));
+        assert_eq!(last.to_fen(), "1n1Rkb1r/p4ppp/4q3/4p1B1/4P3/8/PPP2PPP/2K5 b k - 1 17");
+        assert_eq!(game.san(mainline[22]).unwrap(), "O-O-O");
+
+        assert_eq!(game.nodes[0].comment.as_deref(), Some("Played during a performance of The Barber of Seville."));
+        assert_eq!(game.nodes[mainline[5]].nags, vec![6]);
+        assert!(game.nodes[mainline[5]].comment.as_ref().unwrap().starts_with("Philidor's"));
+        assert_eq!(game.nodes[mainline[18]].nags, vec![1]);
+        assert_eq!(game.nodes[mainline[30]].nags, vec![3]);
+
+        let variation = game.nodes[mainline[6]].children[1];
+        assert_eq!(game.san(variation).unwrap(), "dxe5");
+        let mut line_end = variation;
+        while let Some(child) = game.nodes[line_end].children.first() {
+            line_end = *child;
+        }
+        assert_eq!(game.san(line_end).unwrap(), "Nxe5");
+        assert_eq!(game.nodes[line_end].nags, vec![16]);
+    }
+
+    #[test]
+    fn pgn_tracks_history_along_a_line() {
+        let game = Game::from_pgn("1. Nf3 Nf6 2. Ng1 Ng8 (2... Nh5 3. Nf3) 3. Nf3 Nf6 4. Ng1 Ng8 *").unwrap();
+        let last = *game.mainline().last().unwrap();
+        let moves = game.moves_to(last);
+        assert_eq!(moves, ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2));
+        let start = game.nodes[0].position;
+        let (position,history) = start.play_uci_moves(&moves).unwrap();
+        assert_eq!(game.history(last), history);
+        assert_eq!(get_game_result(&position, &game.history(last)), GameResult::Draw(DrawReason::ThreefoldRepetition));
+
+        let variation_end = game.nodes[game.nodes[game.mainline()[2]].children[1]].children[0];
+        assert_eq!(game.moves_to(variation_end), ["g1f3", "g8f6", "f3g1", "f6h5", "g1f3"]);
+        assert_eq!(game.history(variation_end).len(), 5);
+        assert!(game.history(0).is_empty());
+    }
+
+    #[test]
+    fn pgn_export_round_trips() {
+        let game = Game::from_pgn(OPERA_GAME).unwrap();
+        let pgn = game.to_pgn();
+        assert_eq!(Game::from_pgn(&pgn), Ok(game));
+        assert!(pgn.lines().all(|line| line.len() < 80), "{pgn}");
+        let movetext = pgn.split_whitespace().collect::<Vec<&str>>().join(" ");
+        assert!(movetext.contains("4. dxe5 Bxf3 (4... dxe5 5. Qxd8+ Kxd8 6. Nxe5 $16) 5. Qxf3"), "{pgn}");
+        assert!(movetext.contains("Nxd7 (15... Qxd7 16. Qb8+ Ke7 17. Qxe5+) 16. Qb8+ $3 Nxb8 17. Rd8# 1-0"), "{pgn}");
+    }
+
+    #[test]
+    fn pgn_writes_export_format() {
+        let mut game = Game::new(Position::from_fen(START_FEN).unwrap());
+        let mut node = 0;
+        for san in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+            let chess_move = parse_san(&game.nodes[node].position, san).unwrap();
+            node = game.add_move(node, chess_move).unwrap();
+        }
+        game.nodes[node].comment = Some("Scholar's mate".to_owned());
+        game.set_tag("White", "Engine");
+        game.set_tag("Result", "1-0");
+        assert_eq!(game.to_pgn(), "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"Engine\"]\n[Black \"?\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# {Scholar's mate} 1-0\n");
+
+        let mut game = Game::new(Position::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap());
+        let chess_move = parse_san(&game.nodes[0].position, "Kd7").unwrap();
+        game.add_move(0, chess_move).unwrap();
+        let pgn = game.to_pgn();
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n"), "{pgn}");
+        assert!(pgn.ends_with("\n40... Kd7 *\n"), "{pgn}");
+        assert_eq!(Game::from_pgn(&pgn).unwrap().nodes, game.nodes);
+    }
+
+    #[test]
+    fn pgn_reads_several_games_and_reports_errors() {
+        let games = read_games("[White \"A\"]\n\n1. d4 d5 1/2-1/2\n\n[White \"B\"]\n\n1.e4 c5 2.Nf3 ; Open Sicilian next\n*\n").unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].result(), "1/2-1/2");
+        assert_eq!(games[1].tag("White"), Some("B"));
+        assert_eq!(games[1].nodes[games[1].mainline()[2]].comment.as_deref(), Some("Open Sicilian next"));
+
+        assert_eq!(Game::from_pgn("1. e4 e5 2. Ke3"), Err(PgnError::BadMove { san: "Ke3".to_owned(), error: SanError::NoMatchingMove("Ke3".to_owned()) }));
+        assert_eq!(Game::from_pgn("1. e4 {unfinished"), Err(PgnError::UnterminatedComment));
+        assert_eq!(Game::from_pgn("1. e4 (1. d4"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(Game::from_pgn("(1. d4)"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(Game::from_pgn("[White \"A\"\n1. e4"), Err(PgnError::UnterminatedTag));
+        assert_eq!(Game::from_pgn("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]"), Err(PgnError::BadFen(FenError::MissingKing(Color::White))));
+        assert_eq!(Game::from_pgn("1. e4 & e5"), Err(PgnError::UnexpectedCharacter('&')));
+    }
+}
@@ -0,0 +1,38 @@
+use super::{chess_move::Move, position::Position};
+
+//Counts the leaf nodes of the legal move tree, the standard way to check a move generator against known numbers
+pub fn perft(position: &Position,depth: u8) -> u64 {
+    let mut position = *position;
+    perft_in_place(&mut position, depth)
+}
+
+fn perft_in_place(position: &mut Position,depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = position.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for chess_move in moves {
+        if let Ok(undo) = position.make(chess_move) {
+            nodes += perft_in_place(position, depth - 1);
+            position.unmake(&undo);
+        }
+    }
+    nodes
+}
+
+//Perft split up by root move, comparing this against another engine points straight at the move that is wrong
+pub fn divide(position: &Position,depth: u8) -> Vec<(Move,u64)> {
+    let mut position = *position;
+    let mut divided: Vec<(Move,u64)> = vec![];
+    for chess_move in position.legal_moves() {
+        if let Ok(undo) = position.make(chess_move) {
+            divided.push((chess_move,perft_in_place(&mut position, depth.saturating_sub(1))));
+            position.unmake(&undo);
+        }
+    }
+    divided
+}
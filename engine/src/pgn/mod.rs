@@ -0,0 +1,417 @@
+use core::fmt;
+use std::{error::Error, iter::Peekable, str::Chars};
+
+use super::{chess_move::Move, position::{FenError, Position, START_FEN}, san::{move_to_san, parse_san, SanError}, Color};
+
+//The tags every PGN game is expected to have, written first and in this order
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*")
+];
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+const MAX_LINE_LENGTH: usize = 79;
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum PgnError {
+    UnterminatedTag,
+    UnterminatedComment,
+    BadTag(String),
+    BadFen(FenError),
+    BadMove { san: String, error: SanError },
+    UnexpectedCharacter(char),
+    UnbalancedVariation
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::UnterminatedTag => write!(f, "A tag is missing its closing bracket"),
+            PgnError::UnterminatedComment => write!(f, "A comment is missing its closing brace"),
+            PgnError::BadTag(tag) => write!(f, "\"{tag}\" is not a valid tag"),
+            PgnError::BadFen(error) => write!(f, "Invalid FEN tag: {error}"),
+            PgnError::BadMove { error, .. } => write!(f, "{error}"),
+            PgnError::UnexpectedCharacter(c) => write!(f, "Unexpected '{c}' in the movetext"),
+            PgnError::UnbalancedVariation => write!(f, "Variation brackets do not match up")
+        }
+    }
+}
+
+impl Error for PgnError {}
+
+//One position in the game tree, the first child is the main line and the others are variations
+#[derive(Clone,PartialEq,Debug)]
+pub struct GameNode {
+    pub position: Position,
+    pub chess_move: Option<Move>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub nags: Vec<u8>,
+    pub starting_comment: Option<String>,
+    pub comment: Option<String>
+}
+
+//A game with its tags and every line that was played or analysed, nodes[0] is the starting position
+#[derive(Clone,PartialEq,Debug)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub nodes: Vec<GameNode>
+}
+
+impl Game {
+    pub fn new(start: Position) -> Game {
+        let mut game = Game {
+            tags: Vec::new(),
+            nodes: vec![GameNode { position: start, chess_move: None, parent: None, children: Vec::new(), nags: Vec::new(), starting_comment: None, comment: None }]
+        };
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+        game
+    }
+
+    pub fn tag(&self,name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self,name: &str,value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_owned(),
+            None => self.tags.push((name.to_owned(), value.to_owned()))
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        self.tag("Result").unwrap_or("*")
+    }
+
+    //Plays a move after the given node, reusing the node if that move is already in the tree
+    pub fn add_move(&mut self,parent: usize,chess_move: Move) -> Result<usize,SanError> {
+        if let Some(existing) = self.nodes[parent].children.iter().find(|child| self.nodes[**child].chess_move == Some(chess_move)) {
+            return Ok(*existing);
+        }
+        let position = self.nodes[parent].position.make_move(chess_move)
+            .map_err(|_| SanError::NoMatchingMove(chess_move.to_string()))?;
+        let node = self.nodes.len();
+        self.nodes.push(GameNode { position, chess_move: Some(chess_move), parent: Some(parent), children: Vec::new(), nags: Vec::new(), starting_comment: None, comment: None });
+        self.nodes[parent].children.push(node);
+        Ok(node)
+    }
+
+    //The nodes of the main line after the starting position
+    pub fn mainline(&self) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut node = 0;
+        while let Some(child) = self.nodes[node].children.first() {
+            line.push(*child);
+            node = *child;
+        }
+        line
+    }
+
+    //The nodes leading from the start to this one, the start itself left out
+    pub fn path_to(&self,node: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut current = node;
+        while let Some(parent) = self.nodes[current].parent {
+            path.push(current);
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    //The moves played to reach this node from the start, in coordinate notation
+    pub fn moves_to(&self,node: usize) -> Vec<String> {
+        self.path_to(node).iter().filter_map(|node| self.nodes[*node].chess_move).map(|chess_move| chess_move.to_string()).collect()
+    }
+
+    //Keys of every position before this one, oldest first, for the repetition rules
+    pub fn history(&self,node: usize) -> Vec<u64> {
+        let mut history = vec![self.nodes[0].position.key()];
+        history.extend(self.path_to(node).iter().map(|node| self.nodes[*node].position.key()));
+        history.pop();
+        history
+    }
+
+    pub fn san(&self,node: usize) -> Option<String> {
+        let chess_move = self.nodes[node].chess_move?;
+        let parent = self.nodes[node].parent?;
+        Some(move_to_san(&self.nodes[parent].position, chess_move))
+    }
+
+    pub fn from_pgn(pgn: &str) -> Result<Game,PgnError> {
+        let mut parser = Parser { chars: pgn.chars().peekable() };
+        parser.game()
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, default) in SEVEN_TAG_ROSTER {
+            pgn.push_str(&tag_line(name, self.tag(name).unwrap_or(default)));
+        }
+        for (name, value) in &self.tags {
+            if !SEVEN_TAG_ROSTER.iter().any(|(roster_name, _)| roster_name == name) {
+                pgn.push_str(&tag_line(name, value));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.nodes[0].comment {
+            push_comment(&mut tokens, comment);
+        }
+        self.write_line(0, &mut tokens, true);
+        tokens.push(self.result().to_owned());
+        pgn.push_str(&wrap(&tokens));
+        pgn.push('\n');
+        pgn
+    }
+
+    fn write_line(&self,mut node: usize,tokens: &mut Vec<String>,mut force_number: bool) {
+        while let Some(main) = self.nodes[node].children.first() {
+            force_number = self.write_move(*main, tokens, force_number);
+            for variation in &self.nodes[node].children[1..] {
+                tokens.push("(".to_owned());
+                let force_number = self.write_move(*variation, tokens, true);
+                self.write_line(*variation, tokens, force_number);
+                tokens.push(")".to_owned());
+            }
+            force_number |= self.nodes[node].children.len() > 1;
+            node = *main;
+        }
+    }
+
+    //Returns whether the next move needs its number repeated because something came in between
+    fn write_move(&self,node: usize,tokens: &mut Vec<String>,force_number: bool) -> bool {
+        let game_node = &self.nodes[node];
+        let before = &self.nodes[game_node.parent.unwrap_or(0)].position;
+        if let Some(comment) = &game_node.starting_comment {
+            push_comment(tokens, comment);
+        }
+        //The number stays on the same line as its move
+        let san = self.san(node).unwrap_or_default();
+        match before.whos_move {
+            Color::White => tokens.push(format!("{}. {san}", before.fullmove_number)),
+            Color::Black if force_number || game_node.starting_comment.is_some() => tokens.push(format!("{}... {san}", before.fullmove_number)),
+            Color::Black => tokens.push(san)
+        }
+        for nag in &game_node.nags {
+            tokens.push(format!("${nag}"));
+        }
+        if let Some(comment) = &game_node.comment {
+            push_comment(tokens, comment);
+            return true;
+        }
+        false
+    }
+}
+
+//Reads every game in a PGN file
+pub fn read_games(pgn: &str) -> Result<Vec<Game>,PgnError> {
+    let mut parser = Parser { chars: pgn.chars().peekable() };
+    let mut games = Vec::new();
+    loop {
+        parser.skip_whitespace();
+        if parser.chars.peek().is_none() {
+            return Ok(games);
+        }
+        games.push(parser.game()?);
+    }
+}
+
+fn tag_line(name: &str,value: &str) -> String {
+    format!("[{name} \"{}\"]\n", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn push_comment(tokens: &mut Vec<String>,comment: &str) {
+    let words: Vec<&str> = comment.split_whitespace().collect();
+    match words.len() {
+        0 => tokens.push("{}".to_owned()),
+        1 => tokens.push(format!("{{{}}}", words[0])),
+        count => {
+            tokens.push(format!("{{{}", words[0]));
+            tokens.extend(words[1..count - 1].iter().map(|word| word.to_string()));
+            tokens.push(format!("{}}}", words[count - 1]));
+        }
+    }
+}
+
+//Joins the movetext into lines short enough for the export format, brackets hug what they enclose
+fn wrap(tokens: &[String]) -> String {
+    let mut text = String::new();
+    let mut line_length = 0;
+    let mut after_open = true;
+    for token in tokens {
+        let spaced = !after_open && token != ")";
+        if spaced && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+            text.push('\n');
+            line_length = 0;
+        }
+        else if spaced {
+            text.push(' ');
+            line_length += 1;
+        }
+        text.push_str(token);
+        line_length += token.len();
+        after_open = token == "(";
+    }
+    text
+}
+
+//Short hand annotations that PGN stores as numeric annotation glyphs
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "+#=:-/_*".contains(c)
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn read_while(&mut self,keep: impl Fn(char) -> bool) -> String {
+        let mut text = String::new();
+        while let Some(c) = self.chars.next_if(|c| keep(*c)) {
+            text.push(c);
+        }
+        text
+    }
+
+    fn tag(&mut self) -> Result<(String, String),PgnError> {
+        self.chars.next();
+        self.skip_whitespace();
+        let name = self.read_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        self.skip_whitespace();
+        if name.is_empty() || self.chars.next() != Some('"') {
+            let rest = self.read_while(|c| c != ']' && c != '\n');
+            return Err(PgnError::BadTag(format!("{name}{rest}")));
+        }
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\\') => value.extend(self.chars.next()),
+                Some('"') => break,
+                Some(c) => value.push(c),
+                None => return Err(PgnError::UnterminatedTag)
+            }
+        }
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(']') => Ok((name, value)),
+            _ => Err(PgnError::UnterminatedTag)
+        }
+    }
+
+    fn comment(&mut self) -> Result<String,PgnError> {
+        self.chars.next();
+        let text = self.read_while(|c| c != '}');
+        if self.chars.next().is_none() {
+            return Err(PgnError::UnterminatedComment);
+        }
+        Ok(text.split_whitespace().collect::<Vec<&str>>().join(" "))
+    }
+
+    fn game(&mut self) -> Result<Game,PgnError> {
+        let mut tags = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('[') => tags.push(self.tag()?),
+                Some('%') => { self.read_while(|c| c != '\n'); },
+                _ => break
+            }
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Position::from_fen(fen).map_err(PgnError::BadFen)?,
+            None => Position::from_fen(START_FEN).map_err(PgnError::BadFen)?
+        };
+        let mut game = Game::new(start);
+        game.tags = tags;
+
+        let mut current = 0;
+        let mut variations: Vec<usize> = Vec::new();
+        let mut after_move = false;
+        let mut pending_comment: Option<String> = None;
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.chars.peek().copied() else { break };
+            match c {
+                '{' | ';' => {
+                    let comment = if c == '{' { self.comment()? } else { self.read_while(|c| c != '\n')[1..].trim().to_owned() };
+                    let slot = if after_move || (current == 0 && variations.is_empty()) { &mut game.nodes[current].comment } else { &mut pending_comment };
+                    *slot = Some(match slot.take() {
+                        Some(earlier) => format!("{earlier} {comment}"),
+                        None => comment
+                    });
+                },
+                '(' => {
+                    self.chars.next();
+                    let parent = game.nodes[current].parent.ok_or(PgnError::UnbalancedVariation)?;
+                    variations.push(current);
+                    current = parent;
+                    after_move = false;
+                },
+                ')' => {
+                    self.chars.next();
+                    current = variations.pop().ok_or(PgnError::UnbalancedVariation)?;
+                    after_move = true;
+                },
+                '$' => {
+                    self.chars.next();
+                    let nag = self.read_while(|c| c.is_ascii_digit());
+                    game.nodes[current].nags.push(nag.parse().map_err(|_| PgnError::UnexpectedCharacter('$'))?);
+                },
+                '[' if variations.is_empty() => break,
+                c if is_symbol_char(c) => {
+                    let symbol = self.read_while(is_symbol_char);
+                    let suffix = self.read_while(|c| c == '!' || c == '?');
+                    if symbol.chars().all(|c| c.is_ascii_digit()) {
+                        self.read_while(|c| c == '.' || c.is_whitespace());
+                        continue;
+                    }
+                    if RESULTS.contains(&symbol.as_str()) {
+                        game.set_tag("Result", &symbol);
+                        break;
+                    }
+                    let chess_move = parse_san(&game.nodes[current].position, &symbol)
+                        .map_err(|error| PgnError::BadMove { san: symbol.clone(), error })?;
+                    current = game.add_move(current, chess_move).map_err(|error| PgnError::BadMove { san: symbol.clone(), error })?;
+                    if let Some(comment) = pending_comment.take() {
+                        game.nodes[current].starting_comment = Some(comment);
+                    }
+                    game.nodes[current].nags.extend(suffix_nag(&suffix));
+                    after_move = true;
+                },
+                c => return Err(PgnError::UnexpectedCharacter(c))
+            }
+        }
+
+        if !variations.is_empty() {
+            return Err(PgnError::UnbalancedVariation);
+        }
+        Ok(game)
+    }
+}
@@ -0,0 +1,441 @@
+use core::fmt;
+use std::error::Error;
+
+use super::{bitboard::{bit, pawn_attacks, squares, Bitboard}, chess_move::{square_name, Move, UciMoveError}, is_in_check, transposition_table::position_keys, parse_square, CastlingRights, ChessEngineError, Color, Piece, PieceType,
+    BLACK_BISHOP, BLACK_KING, BLACK_KNIGHT, BLACK_PAWN, BLACK_QUEEN, BLACK_ROOK, WHITE_BISHOP, WHITE_KING, WHITE_KNIGHT, WHITE_PAWN, WHITE_QUEEN, WHITE_ROOK};
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+//a8 is a light square and the colors alternate from there
+const LIGHT_SQUARES: Bitboard = 0xaa55_aa55_aa55_aa55;
+const PIECE_TYPES: [PieceType; 6] = [PieceType::Pawn,PieceType::Knight,PieceType::Bishop,PieceType::Rook,PieceType::Queen,PieceType::King];
+
+//Whatever make overwrites that cannot be worked out again from the move itself
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub struct Undo {
+    pub chess_move: Move,
+    pub moved_piece: Piece,
+    pub captured_piece: Option<Piece>,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<usize>,
+    pub halfmove_clock: u32,
+    pub key: u64
+}
+
+//Everything that can be wrong with a FEN string, ranks are counted the way FEN writes them, 8 first
+#[derive(Clone,PartialEq,Debug)]
+pub enum FenError {
+    Empty,
+    TooManyFields(usize),
+    BadRankCount(usize),
+    BadRankLength { rank: usize, squares: usize },
+    BadPieceChar(char),
+    MissingKing(Color),
+    TooManyKings(Color),
+    BadSideToMove(String),
+    BadCastlingField(String),
+    BadEnPassantSquare(String),
+    BadClocks(String)
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "FEN is empty"),
+            FenError::TooManyFields(count) => write!(f, "FEN has {count} fields, at most 6 are allowed"),
+            FenError::BadRankCount(count) => write!(f, "Piece placement has {count} ranks instead of 8"),
+            FenError::BadRankLength { rank, squares } => write!(f, "Rank {rank} covers {squares} squares instead of 8"),
+            FenError::BadPieceChar(c) => write!(f, "'{c}' is not a piece"),
+            FenError::MissingKing(color) => write!(f, "{color:?} has no king"),
+            FenError::TooManyKings(color) => write!(f, "{color:?} has more than one king"),
+            FenError::BadSideToMove(field) => write!(f, "Side to move has to be w or b, not \"{field}\""),
+            FenError::BadCastlingField(field) => write!(f, "\"{field}\" is not a valid castling field"),
+            FenError::BadEnPassantSquare(field) => write!(f, "\"{field}\" is not a valid en passant square"),
+            FenError::BadClocks(field) => write!(f, "\"{field}\" is not a valid move counter")
+        }
+    }
+}
+
+impl Error for FenError {}
+
+fn piece_from_char(c: char) -> Option<Piece> {
+    match c {
+        'P' => Some(WHITE_PAWN),
+        'R' => Some(WHITE_ROOK),
+        'N' => Some(WHITE_KNIGHT),
+        'B' => Some(WHITE_BISHOP),
+        'Q' => Some(WHITE_QUEEN),
+        'K' => Some(WHITE_KING),
+        'p' => Some(BLACK_PAWN),
+        'r' => Some(BLACK_ROOK),
+        'n' => Some(BLACK_KNIGHT),
+        'b' => Some(BLACK_BISHOP),
+        'q' => Some(BLACK_QUEEN),
+        'k' => Some(BLACK_KING),
+        _ => None
+    }
+}
+
+fn piece_char(piece: Piece) -> char {
+    let c = match piece.piece_type {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k'
+    };
+    match piece.color {
+        Color::White => c.to_ascii_uppercase(),
+        Color::Black => c
+    }
+}
+
+//Where the rook starts and ends up when the king castles from one square to the other
+fn castling_rook_squares(king_from: usize,king_to: usize) -> (usize,usize) {
+    if king_to > king_from { (king_from + 3,king_from + 1) } else { (king_from - 4,king_from - 1) }
+}
+
+//Everything a FEN string describes, the board and the rules state always travel together
+//Pieces are kept as one bitboard per color and piece type, indexed by Color and PieceType
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub struct Position {
+    pub pieces: [[Bitboard; 6]; 2],
+    pub occupancy: [Bitboard; 2],
+    pub whos_move: Color,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<usize>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    //Zobrist key of everything above but the move counters, kept up to date by make and unmake
+    key: u64
+}
+
+impl Position {
+    //Fields after the piece placement may be left off, they default to "w - - 0 1" except castling,
+    //which is worked out from where the kings and rooks stand when missing
+    pub fn from_fen(fen: &str) -> Result<Position,FenError> {
+        let fen_parts: Vec<&str> = fen.split_whitespace().collect();
+        let fen_board_part = match fen_parts.first() {
+            Some(part) => *part,
+            None => return Err(FenError::Empty)
+        };
+        if fen_parts.len() > 6 {
+            return Err(FenError::TooManyFields(fen_parts.len()));
+        }
+
+        let fen_whos_move = match fen_parts.get(1) {
+            None | Some(&"w") => Color::White,
+            Some(&"b") => Color::Black,
+            Some(field) => return Err(FenError::BadSideToMove(field.to_string()))
+        };
+        let fen_en_passant = match fen_parts.get(3) {
+            None | Some(&"-") => None,
+            //Only a pawn that just pushed two squares leaves one behind, on the sixth rank when white is to move and the third when black is
+            Some(field) => match parse_square(field) {
+                Some(square) if square / 8 == if fen_whos_move == Color::White { 2 } else { 5 } => Some(square),
+                _ => return Err(FenError::BadEnPassantSquare(field.to_string()))
+            }
+        };
+        let fen_halfmove_clock = match fen_parts.get(4) {
+            None => 0,
+            Some(field) => field.parse().map_err(|_| FenError::BadClocks(field.to_string()))?
+        };
+        let fen_fullmove_number = match fen_parts.get(5) {
+            None => 1,
+            Some(field) => match field.parse() {
+                Ok(number) if number > 0 => number,
+                _ => return Err(FenError::BadClocks(field.to_string()))
+            }
+        };
+
+        let mut position = Position {
+            pieces: [[0; 6]; 2],
+            occupancy: [0; 2],
+            whos_move: fen_whos_move,
+            castling_rights: CastlingRights::default(),
+            en_passant: fen_en_passant,
+            halfmove_clock: fen_halfmove_clock,
+            fullmove_number: fen_fullmove_number,
+            key: 0
+        };
+
+        let fen_ranks: Vec<&str> = fen_board_part.split('/').collect();
+        if fen_ranks.len() != 8 {
+            return Err(FenError::BadRankCount(fen_ranks.len()));
+        }
+        for (row, fen_rank) in fen_ranks.iter().enumerate() {
+            let rank = 8 - row;
+            let mut col = 0;
+            for c in fen_rank.chars() {
+                match c {
+                    '1'..='8' => col += c.to_digit(10).unwrap() as usize,
+                    _ => {
+                        let piece = match piece_from_char(c) {
+                            Some(piece) => piece,
+                            None => return Err(FenError::BadPieceChar(c))
+                        };
+                        if col < 8 {
+                            position.put_piece(row * 8 + col, piece);
+                        }
+                        col += 1;
+                    }
+                }
+            }
+            if col != 8 {
+                return Err(FenError::BadRankLength { rank, squares: col });
+            }
+        }
+
+        for color in [Color::White,Color::Black] {
+            match position.pieces[color as usize][PieceType::King as usize].count_ones() {
+                0 => return Err(FenError::MissingKing(color)),
+                1 => (),
+                _ => return Err(FenError::TooManyKings(color))
+            }
+        }
+
+        //The pawn that pushed has to be standing just past the square
+        if let Some(square) = fen_en_passant {
+            let pushed_to = if fen_whos_move == Color::White { square + 8 } else { square - 8 };
+            if position.pieces[fen_whos_move.opponent() as usize][PieceType::Pawn as usize] & bit(pushed_to) == 0 {
+                return Err(FenError::BadEnPassantSquare(fen_parts[3].to_string()));
+            }
+        }
+
+        position.castling_rights = match fen_parts.get(2) {
+            Some(field) => match CastlingRights::from_fen(field) {
+                Some(castling_rights) => castling_rights,
+                None => return Err(FenError::BadCastlingField(field.to_string()))
+            },
+            None => CastlingRights::from_position(&position)
+        };
+        position.key = position_keys().key(&position);
+        Ok(position)
+    }
+
+    //Writes all six FEN fields, from_fen reads the result back into an identical position
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for row in 0..8 {
+            let mut empty_squares = 0;
+            for col in 0..8 {
+                match self.piece_at(row * 8 + col) {
+                    Some(piece) => {
+                        if empty_squares > 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(piece_char(piece));
+                    },
+                    None => empty_squares += 1
+                }
+            }
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if row < 7 {
+                fen.push('/');
+            }
+        }
+
+        let whos_move = match self.whos_move {
+            Color::White => "w",
+            Color::Black => "b"
+        };
+        let en_passant = match self.en_passant {
+            Some(square) => square_name(square),
+            None => "-".to_owned()
+        };
+        format!("{fen} {whos_move} {} {en_passant} {} {}", self.castling_rights, self.halfmove_clock, self.fullmove_number)
+    }
+
+    pub fn piece_at(&self,square: usize) -> Option<Piece> {
+        let color = if self.occupancy[Color::White as usize] & bit(square) != 0 {
+            Color::White
+        }
+        else if self.occupancy[Color::Black as usize] & bit(square) != 0 {
+            Color::Black
+        }
+        else {
+            return None;
+        };
+        PIECE_TYPES.into_iter()
+            .find(|piece_type| self.pieces[color as usize][*piece_type as usize] & bit(square) != 0)
+            .map(|piece_type| Piece::new(color, piece_type))
+    }
+
+    //Mailbox view of the position, only meant for drawing the board
+    pub fn board(&self) -> [Option<Piece>; 64] {
+        let mut board: [Option<Piece>; 64] = [None; 64];
+        for (square,piece) in board.iter_mut().enumerate() {
+            *piece = self.piece_at(square);
+        }
+        board
+    }
+
+    fn put_piece(&mut self,square: usize,piece: Piece) {
+        self.pieces[piece.color as usize][piece.piece_type as usize] |= bit(square);
+        self.occupancy[piece.color as usize] |= bit(square);
+        self.key ^= position_keys().piece(piece, square);
+    }
+
+    fn remove_piece(&mut self,square: usize) {
+        if let Some(piece) = self.piece_at(square) {
+            self.pieces[piece.color as usize][piece.piece_type as usize] &= !bit(square);
+            self.occupancy[piece.color as usize] &= !bit(square);
+            self.key ^= position_keys().piece(piece, square);
+        }
+    }
+
+    //Plays the move on this position and returns what is needed to take it back, the move is assumed to be legal
+    pub fn make(&mut self,chess_move: Move) -> Result<Undo,ChessEngineError> {
+        let from = chess_move.from as usize;
+        let to = chess_move.to as usize;
+        let piece = match self.piece_at(from) {
+            Some(piece) => piece,
+            None => return Err(ChessEngineError {message: "No piece on the starting square".to_owned()})
+        };
+        let captured_square = if chess_move.is_en_passant() { (from / 8) * 8 + to % 8 } else { to };
+        let undo = Undo {
+            chess_move,
+            moved_piece: piece,
+            captured_piece: self.piece_at(captured_square),
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            key: self.key
+        };
+        let keys = position_keys();
+        self.key ^= keys.en_passant(self.hashed_en_passant());
+
+        self.remove_piece(from);
+        self.remove_piece(captured_square);
+        self.en_passant = None;
+
+        let mut placed_piece = piece;
+        if chess_move.is_castle() {
+            let (rook_from,rook_to) = castling_rook_squares(from, to);
+            self.remove_piece(rook_from);
+            self.put_piece(rook_to, Piece::new(piece.color, PieceType::Rook));
+        }
+        else if piece.piece_type == PieceType::Pawn {
+            if to / 8 == 0 || to / 8 == 7 {
+                placed_piece = Piece::new(piece.color, chess_move.promotion.unwrap_or(PieceType::Queen));
+            }
+            else if chess_move.is_double_push() {
+                self.en_passant = Some((from + to) / 2);
+            }
+        }
+        self.put_piece(to, placed_piece);
+
+        self.halfmove_clock = if piece.piece_type == PieceType::Pawn || chess_move.is_capture() { 0 } else { self.halfmove_clock + 1 };
+        if self.whos_move == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.whos_move = self.whos_move.opponent();
+        self.key ^= keys.side_to_move();
+        self.key ^= keys.en_passant(self.hashed_en_passant());
+        self.key ^= keys.castling(self.castling_rights);
+        self.castling_rights = self.castling_rights.update(from, to);
+        self.key ^= keys.castling(self.castling_rights);
+
+        debug_assert_eq!(self.key, keys.key(self), "key out of step after {chess_move}");
+        Ok(undo)
+    }
+
+    //Takes back the move make returned this undo record for, undos have to be applied newest first
+    pub fn unmake(&mut self,undo: &Undo) {
+        let from = undo.chess_move.from as usize;
+        let to = undo.chess_move.to as usize;
+
+        self.whos_move = self.whos_move.opponent();
+        if self.whos_move == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        self.remove_piece(to);
+        self.put_piece(from, undo.moved_piece);
+        if undo.chess_move.is_castle() {
+            let (rook_from,rook_to) = castling_rook_squares(from, to);
+            self.remove_piece(rook_to);
+            self.put_piece(rook_from, Piece::new(undo.moved_piece.color, PieceType::Rook));
+        }
+        if let Some(captured_piece) = undo.captured_piece {
+            let captured_square = if undo.chess_move.is_en_passant() { (from / 8) * 8 + to % 8 } else { to };
+            self.put_piece(captured_square, captured_piece);
+        }
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.key = undo.key;
+        debug_assert_eq!(self.key, position_keys().key(self), "key out of step after taking back {}", undo.chess_move);
+    }
+
+    //Plays the move on a copy and returns it, handy when the original has to stay around
+    pub fn make_move(&self,chess_move: Move) -> Result<Position,ChessEngineError> {
+        let mut new_position = *self;
+        new_position.make(chess_move)?;
+        Ok(new_position)
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut legal_moves: Vec<Move> = vec![];
+        for square in squares(self.occupancy[self.whos_move as usize]) {
+            let piece = match self.piece_at(square) {
+                Some(piece) => piece,
+                None => continue
+            };
+            if let Ok(moves) = piece.get_legal_moves(self,square) {
+                legal_moves.extend(moves);
+            }
+        }
+        legal_moves
+    }
+
+    //Looks up the legal move going between two squares, promotion is only needed when a pawn reaches the last rank
+    pub fn find_move(&self,from: usize,to: usize,promotion: Option<PieceType>) -> Option<Move> {
+        self.legal_moves().into_iter().find(|chess_move| chess_move.from as usize == from && chess_move.to as usize == to && chess_move.promotion == promotion)
+    }
+
+    //Whether the side to move is in check
+    pub fn is_check(&self) -> bool {
+        is_in_check(self, self.whos_move)
+    }
+
+    //Positions with the same key are the same for the repetition rules
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    //Like Polyglot, the en passant square only counts towards the key when a pawn stands ready to take there
+    pub fn hashed_en_passant(&self) -> Option<usize> {
+        self.en_passant.filter(|square| pawn_attacks(self.whos_move.opponent(), *square) & self.pieces[self.whos_move as usize][PieceType::Pawn as usize] != 0)
+    }
+
+    //Nobody can ever mate with at most a single minor piece left, or with only bishops that all stand on one color
+    pub fn is_insufficient_material(&self) -> bool {
+        let [white, black] = self.pieces;
+        let heavy_or_pawns = [PieceType::Pawn, PieceType::Rook, PieceType::Queen].iter()
+            .any(|piece_type| white[*piece_type as usize] | black[*piece_type as usize] != 0);
+        if heavy_or_pawns {
+            return false;
+        }
+        let knights = white[PieceType::Knight as usize] | black[PieceType::Knight as usize];
+        let bishops = white[PieceType::Bishop as usize] | black[PieceType::Bishop as usize];
+        (knights | bishops).count_ones() <= 1 || (knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0))
+    }
+
+    //Plays coordinate notation moves one after another, also handing back the key of every position passed through
+    pub fn play_uci_moves<S: AsRef<str>>(&self,moves: &[S]) -> Result<(Position,Vec<u64>),UciMoveError> {
+        let mut position = *self;
+        let mut history = Vec::with_capacity(moves.len());
+        for text in moves {
+            let chess_move = Move::from_uci(&position, text.as_ref())?;
+            history.push(position.key());
+            position.make(chess_move).map_err(|_| UciMoveError::IllegalMove(text.as_ref().to_owned()))?;
+        }
+        Ok((position,history))
+    }
+}
@@ -0,0 +1,180 @@
+use core::fmt;
+use std::error::Error;
+
+use super::{chess_move::{square_name, Move}, parse_square, position::Position, PieceType};
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum SanError {
+    BadSyntax(String),
+    NoMatchingMove(String),
+    Ambiguous(String)
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::BadSyntax(san) => write!(f, "\"{san}\" is not a move in algebraic notation"),
+            SanError::NoMatchingMove(san) => write!(f, "\"{san}\" is not a legal move here"),
+            SanError::Ambiguous(san) => write!(f, "\"{san}\" could mean more than one move")
+        }
+    }
+}
+
+impl Error for SanError {}
+
+fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K"
+    }
+}
+
+fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None
+    }
+}
+
+//Writes a legal move the way a scoresheet would, position is the one the move is played from
+pub fn move_to_san(position: &Position,chess_move: Move) -> String {
+    let from = chess_move.from as usize;
+    let to = chess_move.to as usize;
+    let piece_type = match position.piece_at(from) {
+        Some(piece) => piece.piece_type,
+        None => return chess_move.to_string()
+    };
+
+    let mut san = if chess_move.is_castle() {
+        if to > from { "O-O".to_owned() } else { "O-O-O".to_owned() }
+    }
+    else {
+        let mut san = piece_letter(piece_type).to_owned();
+        if piece_type == PieceType::Pawn {
+            if chess_move.is_capture() {
+                san.push_str(&square_name(from)[..1]);
+            }
+        }
+        else {
+            //Only mention as much of the starting square as it takes to tell the pieces apart
+            let rivals: Vec<usize> = position.legal_moves().into_iter()
+                .filter(|other| other.to == chess_move.to && other.from != chess_move.from
+                    && position.piece_at(other.from as usize).map(|piece| piece.piece_type) == Some(piece_type))
+                .map(|other| other.from as usize)
+                .collect();
+            if !rivals.is_empty() {
+                let same_file = rivals.iter().any(|rival| rival % 8 == from % 8);
+                let same_rank = rivals.iter().any(|rival| rival / 8 == from / 8);
+                if !same_file {
+                    san.push_str(&square_name(from)[..1]);
+                }
+                else if !same_rank {
+                    san.push_str(&square_name(from)[1..]);
+                }
+                else {
+                    san.push_str(&square_name(from));
+                }
+            }
+        }
+        if chess_move.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&square_name(to));
+        if let Some(promotion) = chess_move.promotion {
+            san.push('=');
+            san.push_str(piece_letter(promotion));
+        }
+        san
+    };
+
+    if let Ok(new_position) = position.make_move(chess_move) {
+        if new_position.is_check() {
+            san.push(if new_position.legal_moves().is_empty() { '#' } else { '+' });
+        }
+    }
+    san
+}
+
+//Reads a move in algebraic notation, check marks and annotations are optional and "e8Q" works as well as "e8=Q"
+pub fn parse_san(position: &Position,san: &str) -> Result<Move,SanError> {
+    let bad_syntax = || SanError::BadSyntax(san.to_owned());
+    let trimmed = san.trim().trim_end_matches(['+','#','!','?']);
+    let legal_moves = position.legal_moves();
+
+    let castle_to = match trimmed {
+        "O-O" | "0-0" => Some(2),
+        "O-O-O" | "0-0-0" => Some(-2),
+        _ => None
+    };
+    if let Some(offset) = castle_to {
+        return legal_moves.into_iter()
+            .find(|chess_move| chess_move.is_castle() && chess_move.to as i8 - chess_move.from as i8 == offset)
+            .ok_or_else(|| SanError::NoMatchingMove(san.to_owned()));
+    }
+
+    let mut chars: Vec<char> = trimmed.chars().collect();
+    let piece_type = match chars.first().and_then(|letter| piece_from_letter(*letter)) {
+        Some(piece_type) => {
+            chars.remove(0);
+            piece_type
+        },
+        None => PieceType::Pawn
+    };
+
+    let promotion = match chars.last().and_then(|letter| piece_from_letter(*letter)) {
+        Some(promotion) if piece_type == PieceType::Pawn => {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            Some(promotion)
+        },
+        _ => None
+    };
+
+    if chars.len() < 2 {
+        return Err(bad_syntax());
+    }
+    let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+    let to = parse_square(&to).ok_or_else(bad_syntax)?;
+    if chars.last() == Some(&'x') {
+        chars.pop();
+    }
+
+    //Whatever is left can only be the starting file, rank or both
+    let mut from_file: Option<usize> = None;
+    let mut from_rank: Option<usize> = None;
+    for c in chars {
+        match c {
+            'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(c as usize - 'a' as usize),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(7 - (c as usize - '1' as usize)),
+            _ => return Err(bad_syntax())
+        }
+    }
+
+    let candidates: Vec<Move> = legal_moves.into_iter()
+        .filter(|chess_move| {
+            let from = chess_move.from as usize;
+            chess_move.to as usize == to
+                && chess_move.promotion == promotion
+                && !chess_move.is_castle()
+                && position.piece_at(from).map(|piece| piece.piece_type) == Some(piece_type)
+                && from_file.is_none_or(|file| from % 8 == file)
+                && from_rank.is_none_or(|row| from / 8 == row)
+        })
+        .collect();
+
+    match candidates[..] {
+        [chess_move] => Ok(chess_move),
+        [] => Err(SanError::NoMatchingMove(san.to_owned())),
+        _ => Err(SanError::Ambiguous(san.to_owned()))
+    }
+}
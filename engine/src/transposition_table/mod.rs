@@ -0,0 +1,156 @@
+use super::{bitboard::squares, chess_move::Move, position::Position, CastlingRights, Color, Piece};
+
+mod random64;
+use random64::RANDOM64;
+
+//Polyglot's layout: a key per piece kind and square, then castling rights, en passant files and white to move
+const PIECE_KEYS: usize = 768;
+const CASTLING_KEYS: usize = PIECE_KEYS;
+const EN_PASSANT_KEYS: usize = CASTLING_KEYS + 4;
+const TURN_KEY: usize = EN_PASSANT_KEYS + 8;
+const RANDOM_KEYS: usize = TURN_KEY + 1;
+
+//The same keys in every build and every run, so keys can be stored and compared between runs
+static KEYS: ZobristHash = ZobristHash::new();
+
+pub struct ZobristHash {
+    random64: [u64; RANDOM_KEYS]
+}
+
+impl ZobristHash {
+    //Polyglot's own keys, so a position's key is the one opening books are looked up by
+    pub const fn new() -> Self {
+        Self { random64: RANDOM64 }
+    }
+
+    //Polyglot counts kinds black pawn, white pawn, black knight and so on, and squares from a1 along the ranks
+    pub fn piece(&self, piece: Piece, square: usize) -> u64 {
+        self.piece_key(piece.color, piece.piece_type as usize, square)
+    }
+
+    fn piece_key(&self, color: Color, piece_type: usize, square: usize) -> u64 {
+        let kind = piece_type * 2 + (color == Color::White) as usize;
+        self.random64[kind * 64 + (square ^ 56)]
+    }
+
+    pub fn castling(&self, castling_rights: CastlingRights) -> u64 {
+        let rights = [castling_rights.white_king_side,castling_rights.white_queen_side,castling_rights.black_king_side,castling_rights.black_queen_side];
+        self.random64[CASTLING_KEYS..EN_PASSANT_KEYS].iter().zip(rights)
+            .filter(|(_,has_right)| *has_right)
+            .fold(0, |h,(key,_)| h ^ key)
+    }
+
+    //Takes the square from Position::hashed_en_passant, only the file matters
+    pub fn en_passant(&self, en_passant: Option<usize>) -> u64 {
+        en_passant.map_or(0, |square| self.random64[EN_PASSANT_KEYS + square % 8])
+    }
+
+    //Mixed in while white is to move, so toggling it flips the side
+    pub fn side_to_move(&self) -> u64 {
+        self.random64[TURN_KEY]
+    }
+
+    //Everything that decides whether two positions are the same, the move counters are left out.
+    //Position keeps this up to date move by move, this works it out from scratch
+    pub fn key(&self, position: &Position) -> u64 {
+        let mut h = 0;
+
+        for (color,pieces) in [Color::White,Color::Black].into_iter().zip(position.pieces) {
+            for (piece_type,bitboard) in pieces.into_iter().enumerate() {
+                for square in squares(bitboard) {
+                    h ^= self.piece_key(color, piece_type, square);
+                }
+            }
+        }
+        h ^= self.castling(position.castling_rights);
+        h ^= self.en_passant(position.hashed_en_passant());
+        if position.whos_move == Color::White {
+            h ^= self.side_to_move();
+        }
+
+        h
+    }
+}
+
+impl Default for ZobristHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//The keys every Position is hashed with
+pub fn position_keys() -> &'static ZobristHash {
+    &KEYS
+}
+
+const ENTRIES_PER_BUCKET: usize = 4;
+
+//What a stored score says about the real one, scores are from the side to move's point of view like everywhere in the search
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper
+}
+
+#[derive(Clone,PartialEq,Debug,Copy)]
+pub struct TableEntry {
+    check: u32, //The key's upper half, the lower bits already picked the bucket
+    age: u8,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: f32,
+    pub best_move: Option<Move>
+}
+
+type Bucket = [Option<TableEntry>; ENTRIES_PER_BUCKET];
+
+//Kept between searches, a power of two of buckets that fits in the size it was given
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    age: u8
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let fitting = (size_mb.max(1) << 20) / std::mem::size_of::<Bucket>();
+        TranspositionTable { buckets: vec![[None; ENTRIES_PER_BUCKET]; 1 << fitting.ilog2()], age: 0 }
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.fill([None; ENTRIES_PER_BUCKET]);
+        self.age = 0;
+    }
+
+    //Entries from earlier searches stay usable but are the first to be replaced
+    pub fn new_search(&mut self) {
+        self.age = self.age.wrapping_add(1);
+    }
+
+    fn bucket(&self,key: u64) -> usize {
+        key as usize & (self.buckets.len() - 1)
+    }
+
+    pub fn probe(&self,key: u64) -> Option<TableEntry> {
+        let check = (key >> 32) as u32;
+        self.buckets[self.bucket(key)].iter().flatten().find(|entry| entry.check == check).copied()
+    }
+
+    //The same position is always overwritten, otherwise an empty slot is used and then the oldest, shallowest entry
+    pub fn store(&mut self,key: u64,depth: u8,bound: Bound,score: f32,best_move: Option<Move>) {
+        let check = (key >> 32) as u32;
+        let age = self.age;
+        let index = self.bucket(key);
+        let bucket = &mut self.buckets[index];
+
+        let slot = bucket.iter().position(|entry| entry.is_some_and(|entry| entry.check == check))
+            .or_else(|| bucket.iter().position(|entry| entry.is_none()))
+            .unwrap_or_else(|| {
+                let worth = |entry: &Option<TableEntry>| entry.map_or(i32::MIN, |entry| entry.depth as i32 - 4 * age.wrapping_sub(entry.age) as i32);
+                (0..ENTRIES_PER_BUCKET).min_by_key(|slot| worth(&bucket[*slot])).unwrap_or(0)
+            });
+        //A cutoff may not know a move, the one found for this position before is still the best guess
+        let best_move = best_move.or_else(|| bucket[slot].filter(|entry| entry.check == check).and_then(|entry| entry.best_move));
+        bucket[slot] = Some(TableEntry { check, age, depth, bound, score, best_move });
+    }
+}
@@ -0,0 +1,4 @@
+//Built by trunk as a web worker, see index.html
+fn main() {
+    rust_chess_engine::search_worker::run_worker();
+}
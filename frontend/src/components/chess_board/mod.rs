@@ -1,21 +1,81 @@
 use web_sys::DragEvent;
 use yew::{function_component, Properties, Html, html, classes, Callback, use_state, UseStateHandle};
 
-use crate::chess_engine::{Piece,Color, PieceType};
+use chess_engine::{chess_move::Move,position::Position,Color, PieceType};
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct Props {
-    pub board: [Option<Piece>; 64],
-    pub on_piece_drop: Callback<(Option<usize>,usize)>
+    pub position: Position,
+    pub on_piece_drop: Callback<Move>
+}
+
+fn piece_image(color: Color,piece_type: PieceType) -> &'static str {
+    if color == Color::White {
+        match piece_type {
+            PieceType::Pawn => "images/white_pawn.svg",
+            PieceType::Knight => "images/white_knight.svg",
+            PieceType::Bishop => "images/white_bishop.svg",
+            PieceType::Rook => "images/white_rook.svg",
+            PieceType::Queen => "images/white_queen.svg",
+            PieceType::King => "images/white_king.svg",
+        }
+    }
+    else {
+        match piece_type {
+            PieceType::Pawn => "images/black_pawn.svg",
+            PieceType::Knight => "images/black_knight.svg",
+            PieceType::Bishop => "images/black_bishop.svg",
+            PieceType::Rook => "images/black_rook.svg",
+            PieceType::Queen => "images/black_queen.svg",
+            PieceType::King => "images/black_king.svg",
+        }
+    }
 }
 
 #[function_component]
 pub fn ChessBoard(props: &Props) -> Html {
-    let Props { board,on_piece_drop } = props;
+    let Props { position,on_piece_drop } = props;
+    let board = position.board();
 
     let selected_piece_index: UseStateHandle<Option<usize>> = use_state(|| None);
+    let pending_promotion: UseStateHandle<Option<(usize,usize)>> = use_state(|| None);
+
+    //Pawn dropped on the last rank, ask what it should turn into before telling the parent
+    let promotion_picker = match *pending_promotion {
+        None => html! {},
+        Some((from,to)) => {
+            let color = match board[from] {
+                Some(piece) => piece.color,
+                None => Color::White
+            };
+            html! {
+                <div class={classes!("flex mb-4 border border-black border-solid".to_owned())} >
+                    {
+                        [PieceType::Queen,PieceType::Knight,PieceType::Rook,PieceType::Bishop].into_iter().map(|piece_type| {
+                            let choose_piece = {
+                                let pending_promotion = pending_promotion.clone();
+                                let on_piece_drop = on_piece_drop.clone();
+                                let position = *position;
+                                Callback::from(move |_| {
+                                    pending_promotion.set(None);
+                                    if let Some(chess_move) = position.find_move(from, to, Some(piece_type)) {
+                                        on_piece_drop.emit(chess_move);
+                                    }
+                                })
+                            };
+                            html!{ <img class={classes!("w-[62px] h-[62px] cursor-pointer bg-[#fdce9e]".to_owned())}
+                                    onclick={choose_piece}
+                                    src={piece_image(color, piece_type)} /> }
+                        }).collect::<Html>()
+                    }
+                </div>
+            }
+        }
+    };
 
     html! {
+        <>
+        { promotion_picker }
         <div class={classes!("flex flex-col w-[500px] h-[500px]".to_owned())} >
             {
                 (0..8).map(|rowindex| html! {
@@ -32,9 +92,21 @@ pub fn ChessBoard(props: &Props) -> Html {
 
                                 let drop_piece = {
                                     let selected_piece_index = selected_piece_index.clone();
+                                    let pending_promotion = pending_promotion.clone();
                                     let on_piece_drop = on_piece_drop.clone();
+                                    let position = *position;
                                     Callback::from(move |_| {
-                                        on_piece_drop.emit((*selected_piece_index,index));
+                                        let from = match *selected_piece_index {
+                                            Some(from) => from,
+                                            None => return
+                                        };
+                                        if let Some(chess_move) = position.find_move(from, index, None) {
+                                            on_piece_drop.emit(chess_move);
+                                        }
+                                        //Only promotions need a piece picked, so a legal queen promotion means asking first
+                                        else if position.find_move(from, index, Some(PieceType::Queen)).is_some() {
+                                            pending_promotion.set(Some((from,index)));
+                                        }
                                     })
                                 };
 
@@ -46,26 +118,7 @@ pub fn ChessBoard(props: &Props) -> Html {
                                     Some(piece) => piece
                                 };
 
-                                let img_url = if piece.color == Color::White {
-                                    match piece.piece_type {
-                                        PieceType::Pawn => "images/white_pawn.svg",
-                                        PieceType::Knight => "images/white_knight.svg",
-                                        PieceType::Bishop => "images/white_bishop.svg",
-                                        PieceType::Rook => "images/white_rook.svg",
-                                        PieceType::Queen => "images/white_queen.svg",
-                                        PieceType::King => "images/white_king.svg",
-                                    }
-                                }
-                                else {
-                                    match piece.piece_type {
-                                        PieceType::Pawn => "images/black_pawn.svg",
-                                        PieceType::Knight => "images/black_knight.svg",
-                                        PieceType::Bishop => "images/black_bishop.svg",
-                                        PieceType::Rook => "images/black_rook.svg",
-                                        PieceType::Queen => "images/black_queen.svg",
-                                        PieceType::King => "images/black_king.svg",
-                                    }
-                                };
+                                let img_url = piece_image(piece.color, piece.piece_type);
 
                                 let determine_dragged_item = {
                                     let selected_piece_index = selected_piece_index.clone();
@@ -87,5 +140,6 @@ pub fn ChessBoard(props: &Props) -> Html {
                 }).collect::<Html>()
             }
         </div>
+        </>
     }
 }
@@ -0,0 +1,2 @@
+//The app and the search worker are separate wasm binaries, this is the code they share
+pub mod search_worker;
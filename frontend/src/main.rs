@@ -1,110 +1,357 @@
-use crate::{chess_engine::{parse_fen,Color,calculate_with_iterative_deepening}, components::chess_board::ChessBoard};
-mod chess_engine;
+use crate::components::chess_board::ChessBoard;
 mod components;
+mod tauri_engine;
 
-use chess_engine::Piece;
+use std::{cell::RefCell, rc::Rc, sync::atomic::AtomicBool};
+
+use chess_engine::{chess_move::Move,pgn::Game,position::{Position,START_FEN},san::move_to_san,Color,GameResult,SearchControl,SearchProgress,get_game_result,search,transposition_table::TranspositionTable,DEFAULT_DEPTH,DEFAULT_TABLE_MB};
+use rust_chess_engine::search_worker::SearchWorker;
 use wasm_bindgen::JsCast;
 use yew::{prelude::*};
 
 use gloo::{console::log, timers::callback::Timeout};
-use web_sys::HtmlInputElement;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+
+//Plays a move on the board and records it in the game so it can be exported later
+fn play_move(position: &UseStateHandle<Position>,game: &UseStateHandle<Game>,current_node: &UseStateHandle<usize>,chess_move: Move) {
+    let mut new_game = (**game).clone();
+    if let Ok(node) = new_game.add_move(**current_node,chess_move) {
+        position.set(new_game.nodes[node].position);
+        current_node.set(node);
+        game.set(new_game);
+    }
+}
 
-fn computer_moves(board_state_hook: UseStateHandle<[Option<Piece>; 64]>,mut new_board: [Option<Piece>; 64]) {
+//A search in the desktop app cannot be called back once asked for, so a reply is only played
+//if the board is still waiting for that search and has not changed since it started
+#[derive(Default)]
+struct TauriSearch {
+    waiting_for: Option<u64>,
+    next_id: u64
+}
+
+fn play_uci_move(position: &UseStateHandle<Position>,game: &UseStateHandle<Game>,current_node: &UseStateHandle<usize>,text: &str) {
+    match Move::from_uci(position,text) {
+        Ok(best_move) => {
+            log!(format!("Playing {}",move_to_san(position,best_move)));
+            play_move(position,game,current_node,best_move);
+        },
+        Err(error) => log!(error.to_string())
+    }
+}
+
+fn computer_moves(position_hook: UseStateHandle<Position>,game: UseStateHandle<Game>,current_node: UseStateHandle<usize>,
+                thinking: UseStateHandle<Option<String>>,searching: UseStateHandle<bool>,search_worker: Rc<RefCell<Option<SearchWorker>>>,
+                tauri_search: Rc<RefCell<TauriSearch>>) {
     log!("Thinking...");
-    let (best_move_original_position,best_move,_) = calculate_with_iterative_deepening(&new_board,Color::Black,5);
+    searching.set(true);
+    //Searches start from the beginning of the game so they know which positions were already on the board
+    let start_fen = game.nodes[0].position.to_fen();
+    let moves = game.moves_to(*current_node);
+    //The desktop app searches natively off the UI thread
+    if tauri_engine::is_available() {
+        let id = {
+            let mut tauri_search = tauri_search.borrow_mut();
+            let id = tauri_search.next_id;
+            tauri_search.next_id += 1;
+            tauri_search.waiting_for = Some(id);
+            id
+        };
+        spawn_local(async move {
+            let reply = tauri_engine::best_move(&start_fen,&moves,DEFAULT_DEPTH).await;
+            if tauri_search.borrow().waiting_for != Some(id) {
+                return;
+            }
+            tauri_search.borrow_mut().waiting_for = None;
+            match reply {
+                Ok(Some(text)) => play_uci_move(&position_hook,&game,&current_node,&text),
+                Ok(None) => (),
+                Err(error) => log!(error)
+            }
+            searching.set(false);
+        });
+        return;
+    }
 
-    let new_position = best_move_original_position as i8 + best_move;
-    new_board[new_position as usize] = new_board[best_move_original_position];
-    new_board[best_move_original_position] = None;
-    board_state_hook.set(new_board);
+    //The browser build searches in a web worker so the page keeps responding
+    let on_progress = move |progress: SearchProgress| thinking.set(Some(progress_text(&progress)));
+    let on_done = {
+        let position_hook = position_hook.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        let searching = searching.clone();
+        move |best_move: Option<String>| {
+            if let Some(text) = best_move {
+                play_uci_move(&position_hook,&game,&current_node,&text);
+            }
+            searching.set(false);
+        }
+    };
+    let on_failed = {
+        let position_hook = position_hook.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        let searching = searching.clone();
+        let search_worker = search_worker.clone();
+        move || {
+            //Dropped once the worker's error handler has returned, it is still running this
+            Timeout::new(0,move || {
+                search_worker.borrow_mut().take();
+                log!("The search worker failed, searching on the page instead");
+                search_on_page(&position_hook,&game,&current_node,&searching);
+            }).forget();
+        }
+    };
+    let mut worker_slot = search_worker.borrow_mut();
+    let started = match worker_slot.take() {
+        Some(worker) => Ok(worker),
+        None => SearchWorker::new()
+    }.and_then(|mut worker| {
+        worker.start(&start_fen,moves,DEFAULT_DEPTH,on_progress,on_done,on_failed)?;
+        Ok(worker)
+    });
+    match started {
+        Ok(worker) => {
+            *worker_slot = Some(worker);
+            return;
+        },
+        Err(error) => log!(format!("No search worker, searching on the page instead: {error:?}"))
+    }
+    drop(worker_slot);
+    search_on_page(&position_hook,&game,&current_node,&searching);
+}
+
+//Blocks the page until it is done, only for when there is no worker to search in
+fn search_on_page(position_hook: &UseStateHandle<Position>,game: &UseStateHandle<Game>,current_node: &UseStateHandle<usize>,searching: &UseStateHandle<bool>) {
+    let stop = AtomicBool::new(false);
+    let mut control = SearchControl::new(&stop,None).with_history(game.history(**current_node));
+    let (best_move,_) = search(position_hook,DEFAULT_DEPTH,&mut TranspositionTable::new(DEFAULT_TABLE_MB),&mut control,&mut |_| ());
+
+    if let Some(best_move) = best_move {
+        log!(format!("Playing {}",move_to_san(position_hook,best_move)));
+        play_move(position_hook,game,current_node,best_move);
+    }
+    searching.set(false);
+}
+
+fn progress_text(progress: &SearchProgress) -> String {
+    format!("Depth {}: {:+.2} after {} nodes, {}",progress.depth,progress.score,progress.nodes,progress.pv.join(" "))
+}
+
+fn result_tag(game_result: GameResult) -> &'static str {
+    match game_result {
+        GameResult::Ongoing => "*",
+        GameResult::Checkmate(Color::White) => "1-0",
+        GameResult::Checkmate(Color::Black) => "0-1",
+        GameResult::Stalemate | GameResult::Draw(_) => "1/2-1/2"
+    }
 }
 
 #[function_component]
 fn App() -> Html {
-    let board = use_state(|| parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR").0);
-    let whos_move = use_state(|| Color::White);
+    let position = use_state(|| Position::from_fen(START_FEN).expect("start position is valid"));
+    let game = use_state(|| Game::new(*position));
+    let current_node = use_state(|| 0);
+    let fen_error: UseStateHandle<Option<String>> = use_state(|| None);
+    let pgn_text = use_state(String::new);
+    let pgn_error: UseStateHandle<Option<String>> = use_state(|| None);
+    let thinking: UseStateHandle<Option<String>> = use_state(|| None);
+    let searching = use_state(|| false);
+    let search_worker: Rc<RefCell<Option<SearchWorker>>> = use_mut_ref(|| None);
+    let tauri_search: Rc<RefCell<TauriSearch>> = use_mut_ref(TauriSearch::default);
+    let game_result = get_game_result(&position,&game.history(*current_node));
+
+    {
+        let thinking = thinking.clone();
+        use_effect_with_deps(move |_| {
+            if tauri_engine::is_available() {
+                tauri_engine::on_search_progress(move |progress| thinking.set(Some(progress_text(&progress))));
+            }
+        },());
+    }
 
     { //Closure so useEffect works
-        let whos_move = whos_move.clone();
-        let board = board.clone();
-        use_effect_with_deps(move |whos_move| { 
+        let position_hook = position.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        let thinking = thinking.clone();
+        let searching = searching.clone();
+        let search_worker = search_worker.clone();
+        let tauri_search = tauri_search.clone();
+        use_effect_with_deps(move |_| { 
+            let worker_slot = search_worker.clone();
+            let waiting_search = tauri_search.clone();
+            let search_done = searching.clone();
             //Wait for it to be visually noticable that the component has rerendered
-            let whos_move = whos_move.clone();
-            let board_state_hook = board.clone();
             let timeout = Timeout::new(50,move || {
-                match *whos_move {
-                    Color::Black => {computer_moves(board_state_hook, *board); whos_move.set(Color::White)},
+                if get_game_result(&position_hook,&game.history(*current_node)) != GameResult::Ongoing {
+                    return;
+                }
+                match position_hook.whos_move {
+                    Color::Black => computer_moves(position_hook,game,current_node,thinking,searching,search_worker,tauri_search),
                     Color::White => ()
                 };
             });
             timeout.forget();
-        },whos_move);
+
+            //A search for a position that is no longer on the board is thrown away, an idle worker is kept
+            move || {
+                let mut worker_slot = worker_slot.borrow_mut();
+                if worker_slot.as_ref().is_some_and(|worker| worker.is_searching()) {
+                    worker_slot.take();
+                    search_done.set(false);
+                }
+                if waiting_search.borrow_mut().waiting_for.take().is_some() {
+                    tauri_engine::stop();
+                    search_done.set(false);
+                }
+            }
+        },*position);
     }
 
     let submit_fen = {
-        let board = board.clone();
-        let whos_move = whos_move.clone();
+        let position = position.clone();
+        let fen_error = fen_error.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
         Callback::from(move |event: KeyboardEvent| {
-            if event.key() != "Enter".to_owned() {
+            if event.key() != "Enter" {
                 return;
             }
             let target = event.target().unwrap();
             let input = target.unchecked_into::<HtmlInputElement>();
             let value = input.value();
             log!(value.clone());
-            let (result_board,whos_move_from_fen) = parse_fen(&value.clone().trim());
-            board.set(result_board);
-            whos_move.clone().set(whos_move_from_fen);
+            match Position::from_fen(value.trim()) {
+                Ok(new_position) => {
+                    position.set(new_position);
+                    game.set(Game::new(new_position));
+                    current_node.set(0);
+                    fen_error.set(None);
+                },
+                Err(error) => fen_error.set(Some(error.to_string()))
+            }
         })
     };
 
+    //Selecting the whole FEN on focus makes it a single copy away
+    let select_fen = Callback::from(|event: FocusEvent| {
+        event.target_unchecked_into::<HtmlInputElement>().select();
+    });
+
     let on_piece_drop = {
-        let board = board.clone();
-        let whos_move = whos_move.clone();
-        Callback::from(move |from_and_to: (Option<usize>,usize)| {
-            let new_board = *board;
-            let (from,to) = from_and_to;
-            
-            let from = match from {
-                Some(from) => from,
-                None => return
-            };
-
-            let moved_piece = match &new_board[from] {
-                Some(piece) => piece,
-                None => return
-            };
-
-            let movement = to as i8 - from as i8;
-
-            let moves = match moved_piece.get_moves(&new_board) {
-                Ok(moves) => moves,
-                Err(_) => return
-            };
-
-            if moves.contains(&movement) {
-                let new_board = match moved_piece.do_move(&new_board, movement) {
-                    Ok((_,_,new_board)) => new_board,
-                    Err(_) => return
-                };
+        let position = position.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        Callback::from(move |chess_move: Move| {
+            //Black is the engine's side, the board stays live while it thinks
+            if game_result != GameResult::Ongoing || position.whos_move == Color::Black {
+                return;
+            }
 
-                board.set(new_board);
+            if position.legal_moves().contains(&chess_move) {
+                play_move(&position,&game,&current_node,chess_move);
+            }
+        })
+    };
 
-                if *whos_move == Color::Black {
-                    whos_move.set(Color::White);
-                }
-                else {
-                    whos_move.set(Color::Black);
+    //Plays whatever the search has settled on so far
+    let stop_thinking = {
+        let position = position.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        let searching = searching.clone();
+        let search_worker = search_worker.clone();
+        Callback::from(move |_: MouseEvent| {
+            if tauri_engine::is_available() {
+                tauri_engine::stop();
+                return;
+            }
+            let worker = search_worker.borrow_mut().take_if(|worker| worker.is_searching());
+            if let Some(worker) = worker {
+                if let Some(text) = worker.stop() {
+                    play_uci_move(&position,&game,&current_node,&text);
                 }
-                
+                searching.set(false);
+            }
+        })
+    };
+
+    let edit_pgn = {
+        let pgn_text = pgn_text.clone();
+        Callback::from(move |event: InputEvent| {
+            pgn_text.set(event.target_unchecked_into::<HtmlTextAreaElement>().value());
+        })
+    };
+
+    let export_pgn = {
+        let game = game.clone();
+        let pgn_text = pgn_text.clone();
+        let pgn_error = pgn_error.clone();
+        Callback::from(move |_: MouseEvent| {
+            let mut exported = (*game).clone();
+            exported.set_tag("Result", result_tag(game_result));
+            pgn_text.set(exported.to_pgn());
+            pgn_error.set(None);
+        })
+    };
+
+    //An imported game is shown at the end of its main line
+    let import_pgn = {
+        let position = position.clone();
+        let game = game.clone();
+        let current_node = current_node.clone();
+        let pgn_text = pgn_text.clone();
+        let pgn_error = pgn_error.clone();
+        Callback::from(move |_: MouseEvent| {
+            match Game::from_pgn(&pgn_text) {
+                Ok(new_game) => {
+                    let last = new_game.mainline().last().copied().unwrap_or(0);
+                    position.set(new_game.nodes[last].position);
+                    current_node.set(last);
+                    game.set(new_game);
+                    pgn_error.set(None);
+                },
+                Err(error) => pgn_error.set(Some(error.to_string()))
             }
         })
     };
 
+    let game_result_text = match game_result {
+        GameResult::Ongoing => String::new(),
+        GameResult::Checkmate(Color::White) => "Checkmate, White wins".to_owned(),
+        GameResult::Checkmate(Color::Black) => "Checkmate, Black wins".to_owned(),
+        GameResult::Stalemate => "Stalemate, it's a draw".to_owned(),
+        GameResult::Draw(reason) => format!("Draw by {reason}")
+    };
+
     html! {
         <div class="flex flex-col justify-center items-center h-screen" >
-            <input class={classes!("border border-1 border-black border-solid mb-8".to_owned())} onkeypress={submit_fen} />
-            <ChessBoard board={*board} on_piece_drop={on_piece_drop.clone()} />
+            <div class="flex items-center mb-8" >
+                <input class={classes!("border border-1 border-black border-solid".to_owned())} onkeypress={submit_fen} />
+                <p class={classes!("ml-4 text-red-600 w-96".to_owned())} >{ (*fen_error).clone().unwrap_or_default() }</p>
+            </div>
+            <p class={classes!("mb-4 text-xl font-bold h-8".to_owned())} >{ game_result_text }</p>
+            <input class={classes!("border border-1 border-gray-400 border-solid mb-4 w-[500px] text-sm font-mono".to_owned())}
+                readonly=true value={position.to_fen()} onfocus={select_fen} />
+            <p class={classes!("mb-2 h-6 font-mono".to_owned())} >{ game.san(*current_node).map(|san| format!("Last move: {san}")).unwrap_or_default() }</p>
+            <ChessBoard position={*position} on_piece_drop={on_piece_drop.clone()} />
+            <div class="flex items-center mt-2 h-8" >
+                <p class={classes!("text-sm font-mono text-gray-600".to_owned())} >{ (*thinking).clone().unwrap_or_default() }</p>
+                if *searching {
+                    <button class={classes!("border border-1 border-black border-solid px-2 ml-4".to_owned())} onclick={stop_thinking} >{ "Stop thinking" }</button>
+                }
+            </div>
+            <div class="flex items-start mt-8" >
+                <textarea class={classes!("border border-1 border-gray-400 border-solid w-[500px] h-32 text-sm font-mono".to_owned())}
+                    value={(*pgn_text).clone()} oninput={edit_pgn} />
+                <div class="flex flex-col ml-4" >
+                    <button class={classes!("border border-1 border-black border-solid px-2 mb-2".to_owned())} onclick={export_pgn} >{ "Export PGN" }</button>
+                    <button class={classes!("border border-1 border-black border-solid px-2".to_owned())} onclick={import_pgn} >{ "Import PGN" }</button>
+                </div>
+                <p class={classes!("ml-4 text-red-600 w-64".to_owned())} >{ (*pgn_error).clone().unwrap_or_default() }</p>
+            </div>
         </div>
     }
 }
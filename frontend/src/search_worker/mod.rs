@@ -0,0 +1,152 @@
+use std::{cell::{Cell, RefCell}, rc::Rc, sync::atomic::AtomicBool};
+
+use chess_engine::{position::Position, search, transposition_table::TranspositionTable, SearchControl, SearchProgress, DEFAULT_TABLE_MB};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+//Where trunk puts the worker binary's loader next to index.html
+const WORKER_SCRIPT: &str = "search_worker.js";
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+//The game's starting position and the moves played since, the history matters for repetitions
+pub struct SearchRequest {
+    pub fen: String,
+    pub moves: Vec<String>,
+    pub depth: u8
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WorkerMessage {
+    Progress(SearchProgress),
+    Done { best_move: Option<String> },
+    Error { message: String }
+}
+
+//The worker cannot read messages while it searches, so stopping terminates it and keeps
+//the best move from the last iteration it reported. Otherwise it is kept for the next search
+//and so is the transposition table inside it
+pub struct SearchWorker {
+    worker: Worker,
+    searching: Rc<Cell<bool>>,
+    best_so_far: Rc<RefCell<Option<String>>>,
+    on_message: Option<Closure<dyn FnMut(MessageEvent)>>,
+    on_error: Option<Closure<dyn FnMut(JsValue)>>
+}
+
+impl SearchWorker {
+    pub fn new() -> Result<SearchWorker, JsValue> {
+        Ok(SearchWorker {
+            worker: Worker::new(WORKER_SCRIPT)?,
+            searching: Rc::new(Cell::new(false)),
+            best_so_far: Rc::new(RefCell::new(None)),
+            on_message: None,
+            on_error: None
+        })
+    }
+
+    //on_failed is called instead of on_done when the worker's script fails to load or throws,
+    //the worker is no use after that and should be dropped
+    pub fn start(&mut self,fen: &str,moves: Vec<String>,depth: u8,mut on_progress: impl FnMut(SearchProgress) + 'static,
+                on_done: impl FnOnce(Option<String>) + 'static,on_failed: impl FnOnce() + 'static) -> Result<(), JsValue> {
+        *self.best_so_far.borrow_mut() = None;
+
+        let mut on_done = Some(on_done);
+        let best = self.best_so_far.clone();
+        let searching = self.searching.clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            match serde_wasm_bindgen::from_value::<WorkerMessage>(event.data()) {
+                Ok(WorkerMessage::Progress(progress)) => {
+                    *best.borrow_mut() = progress.best_move.clone();
+                    on_progress(progress);
+                },
+                Ok(WorkerMessage::Done { best_move }) => {
+                    searching.set(false);
+                    if let Some(on_done) = on_done.take() {
+                        on_done(best_move);
+                    }
+                },
+                Ok(WorkerMessage::Error { message }) => {
+                    gloo::console::error!(message);
+                    searching.set(false);
+                    if let Some(on_done) = on_done.take() {
+                        on_done(None);
+                    }
+                },
+                Err(error) => gloo::console::error!(error.to_string())
+            }
+        });
+        self.worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        self.on_message = Some(on_message);
+
+        let mut on_failed = Some(on_failed);
+        let searching = self.searching.clone();
+        let on_error = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+            gloo::console::error!(event);
+            if searching.replace(false) {
+                if let Some(on_failed) = on_failed.take() {
+                    on_failed();
+                }
+            }
+        });
+        self.worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        self.on_error = Some(on_error);
+
+        let request = SearchRequest { fen: fen.to_owned(), moves, depth };
+        self.worker.post_message(&serde_wasm_bindgen::to_value(&request)?)?;
+        self.searching.set(true);
+        Ok(())
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching.get()
+    }
+
+    pub fn stop(self) -> Option<String> {
+        self.best_so_far.borrow_mut().take()
+    }
+}
+
+impl Drop for SearchWorker {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}
+
+fn post(scope: &DedicatedWorkerGlobalScope,message: &WorkerMessage) {
+    if let Ok(message) = serde_wasm_bindgen::to_value(message) {
+        //Nothing to do if the page has gone away
+        let _ = scope.post_message(&message);
+    }
+}
+
+//Runs inside the worker, every request is searched to its depth and answered with progress and then done
+pub fn run_worker() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let reply_to = scope.clone();
+    //The worker lives until the search is stopped, so the table carries over between the moves it plays
+    let mut table = TranspositionTable::new(DEFAULT_TABLE_MB);
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        let request = match serde_wasm_bindgen::from_value::<SearchRequest>(event.data()) {
+            Ok(request) => request,
+            Err(error) => return post(&reply_to, &WorkerMessage::Error { message: error.to_string() })
+        };
+        let start = match Position::from_fen(&request.fen) {
+            Ok(start) => start,
+            Err(error) => return post(&reply_to, &WorkerMessage::Error { message: error.to_string() })
+        };
+        let (position, history) = match start.play_uci_moves(&request.moves) {
+            Ok(replayed) => replayed,
+            Err(error) => return post(&reply_to, &WorkerMessage::Error { message: error.to_string() })
+        };
+
+        let stop = AtomicBool::new(false);
+        let (best_move, _) = search(&position, request.depth, &mut table, &mut SearchControl::new(&stop, None).with_history(history), &mut |info| {
+            post(&reply_to, &WorkerMessage::Progress(SearchProgress::from(info)));
+        });
+        post(&reply_to, &WorkerMessage::Done { best_move: best_move.map(|best_move| best_move.to_string()) });
+    });
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+}
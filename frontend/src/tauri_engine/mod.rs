@@ -0,0 +1,63 @@
+use chess_engine::SearchProgress;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsValue};
+use wasm_bindgen_futures::spawn_local;
+
+//The desktop app injects window.__TAURI__ because withGlobalTauri is on, the browser build has no such thing
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "tauri"], catch)]
+    async fn invoke(command: &str, args: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(js_namespace = ["window", "__TAURI__", "event"])]
+    async fn listen(event: &str, handler: &Closure<dyn FnMut(JsValue)>) -> JsValue;
+}
+
+#[derive(Deserialize)]
+struct TauriEvent<T> {
+    payload: T
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BestMoveArgs<'a> {
+    fen: &'a str,
+    moves: &'a [String],
+    depth: Option<u8>,
+    movetime_ms: Option<u64>
+}
+
+pub fn is_available() -> bool {
+    web_sys::window()
+        .and_then(|window| js_sys::Reflect::get(&window, &JsValue::from_str("__TAURI__")).ok())
+        .is_some_and(|tauri| !tauri.is_undefined())
+}
+
+//Searches natively on the backend's thread pool from the position after the moves, None when it has no moves
+pub async fn best_move(fen: &str,moves: &[String],depth: u8) -> Result<Option<String>, String> {
+    let args = serde_wasm_bindgen::to_value(&BestMoveArgs { fen, moves, depth: Some(depth), movetime_ms: None }).map_err(|error| error.to_string())?;
+    let result = invoke("best_move", args).await.map_err(|error| error.as_string().unwrap_or_else(|| format!("{error:?}")))?;
+    serde_wasm_bindgen::from_value(result).map_err(|error| error.to_string())
+}
+
+//The listener lives as long as the page does
+pub fn on_search_progress(mut callback: impl FnMut(SearchProgress) + 'static) {
+    let handler = Closure::<dyn FnMut(JsValue)>::new(move |event: JsValue| {
+        if let Ok(event) = serde_wasm_bindgen::from_value::<TauriEvent<SearchProgress>>(event) {
+            callback(event.payload);
+        }
+    });
+    spawn_local(async move {
+        listen("search-progress", &handler).await;
+        handler.forget();
+    });
+}
+
+//Whatever the running search has found so far is what best_move resolves with
+pub fn stop() {
+    spawn_local(async {
+        if let Err(error) = invoke("stop", JsValue::from(js_sys::Object::new())).await {
+            gloo::console::error!(error);
+        }
+    });
+}
@@ -1,8 +1,107 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::{sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}, thread, time::{Duration, Instant}};
+
+use chess_engine::{chess_move::Move, position::Position, search, transposition_table::TranspositionTable, SearchControl, SearchProgress, DEFAULT_DEPTH, DEFAULT_TABLE_MB, MAX_DEPTH};
+use tauri::{State, Window};
+
+//Only one search runs at a time, starting another stops the one before.
+//The table lives as long as the app so later searches build on earlier ones
+struct SearchState {
+  stop: Mutex<Arc<AtomicBool>>,
+  table: Arc<Mutex<TranspositionTable>>
+}
+
+impl Default for SearchState {
+  fn default() -> SearchState {
+    SearchState {
+      stop: Mutex::default(),
+      table: Arc::new(Mutex::new(TranspositionTable::new(DEFAULT_TABLE_MB)))
+    }
+  }
+}
+
+impl SearchState {
+  fn stop(&self) {
+    self.stop.lock().expect("search state lock poisoned").store(true, Ordering::Relaxed);
+  }
+
+  //Stops whatever is running and hands out the flag for the next search
+  fn restart(&self) -> Arc<AtomicBool> {
+    let mut stop = self.stop.lock().expect("search state lock poisoned");
+    stop.store(true, Ordering::Relaxed);
+    *stop = Arc::new(AtomicBool::new(false));
+    stop.clone()
+  }
+}
+
+fn parse_position(fen: &str) -> Result<Position, String> {
+  Position::from_fen(fen).map_err(|error| error.to_string())
+}
+
+//The game so far as its starting position and the moves played since, so the search knows which positions were already seen
+fn replay(fen: &str, moves: &[String]) -> Result<(Position, Vec<u64>), String> {
+  parse_position(fen)?.play_uci_moves(moves).map_err(|error| error.to_string())
+}
+
+fn run_search(window: &Window, table: &Mutex<TranspositionTable>, position: Position, history: Vec<u64>, depth: u8, deadline: Option<Instant>, stop: &AtomicBool) -> Option<Move> {
+  let mut control = SearchControl::new(stop, deadline).with_history(history);
+  //A search that was just stopped lets go of the table once it has finished unwinding
+  let mut table = table.lock().expect("table lock poisoned");
+  let (best_move, _) = search(&position, depth, &mut table, &mut control, &mut |info| {
+    //Sent to the frontend as "search-progress" after every finished iteration, a closed window has nobody left to tell
+    let _ = window.emit("search-progress", SearchProgress::from(info));
+  });
+  best_move
+}
+
+#[tauri::command]
+fn legal_moves(fen: String) -> Result<Vec<String>, String> {
+  let position = parse_position(&fen)?;
+  Ok(position.legal_moves().iter().map(|chess_move| chess_move.to_string()).collect())
+}
+
+//Resolves with the move to play after the moves from fen, or null when there is none, the search runs off the async runtime
+#[tauri::command]
+async fn best_move(window: Window, state: State<'_, SearchState>, fen: String, moves: Option<Vec<String>>, depth: Option<u8>, movetime_ms: Option<u64>) -> Result<Option<String>, String> {
+  let (position, history) = replay(&fen, &moves.unwrap_or_default())?;
+  let stop = state.restart();
+  let deadline = movetime_ms.map(|movetime| Instant::now() + Duration::from_millis(movetime));
+  let depth = match (depth, deadline) {
+    (Some(depth), _) => depth.clamp(1, MAX_DEPTH),
+    (None, Some(_)) => MAX_DEPTH,
+    (None, None) => DEFAULT_DEPTH
+  };
+  let table = state.table.clone();
+  tauri::async_runtime::spawn_blocking(move || run_search(&window, &table, position, history, depth, deadline, &stop))
+    .await
+    .map(|best_move| best_move.map(|best_move| best_move.to_string()))
+    .map_err(|error| error.to_string())
+}
+
+//Keeps deepening until stop is called, progress and the final "analysis-done" come as events
+#[tauri::command]
+fn analyze(window: Window, state: State<'_, SearchState>, fen: String, moves: Option<Vec<String>>) -> Result<(), String> {
+  let (position, history) = replay(&fen, &moves.unwrap_or_default())?;
+  let stop = state.restart();
+  let table = state.table.clone();
+  thread::spawn(move || {
+    let best_move = run_search(&window, &table, position, history, MAX_DEPTH, None, &stop);
+    let _ = window.emit("analysis-done", best_move.map(|best_move| best_move.to_string()));
+  });
+  Ok(())
+}
+
+#[tauri::command]
+fn stop(state: State<'_, SearchState>) {
+  state.stop();
+}
+
 fn main() {
   tauri::Builder::default()
+    .manage(SearchState::default())
+    .invoke_handler(tauri::generate_handler![analyze, best_move, stop, legal_moves])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
 }